@@ -0,0 +1,101 @@
+//! How long it takes to scan a show's episodes compared to looking
+//! them up in the index, for large synthetic shows.
+//!
+//! Run with: `cargo bench --bench episodes`
+
+use pls::state::State;
+
+use std::{
+    fs,
+    hint::black_box,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use toml_edit::Document;
+
+/// Number of seasons and episodes per season of the generated shows.
+const SHOWS: &[(usize, usize)] = &[(10, 100), (20, 500), (50, 1000)];
+
+fn main() -> anyhow::Result<()> {
+    let root = std::env::temp_dir().join(format!("pls-bench-{}", std::process::id()));
+    let result = run(&root);
+    let _ = fs::remove_dir_all(&root);
+    result
+}
+
+fn run(root: &Path) -> anyhow::Result<()> {
+    for &(seasons, episodes) in SHOWS {
+        let key = format!("show-{}", seasons * episodes);
+        let config_path = generate_show(root, &key, seasons, episodes)?;
+        let mut show = State::load_show_file(&config_path, &key, None, &Document::new())?;
+        println!("{} episodes:", show.episodes().len());
+
+        bench("scan the directory", || {
+            show.refresh_episodes();
+        });
+        bench("check if the index is stale", || {
+            black_box(show.index.is_stale());
+        });
+        bench("list the episodes", || {
+            black_box(show.episodes().len());
+        });
+        // NOTE: what the window needs from the show every frame.
+        bench("draw a frame", || {
+            black_box(show.previous_episode());
+            black_box(show.unwatched_count());
+            black_box(show.current_episode());
+        });
+        println!();
+    }
+    Ok(())
+}
+
+/// Create a show directory with `seasons` subdirectories holding
+/// `episodes` files each and its config file. The next episode is in
+/// the middle of the show. Returns the path to the config file.
+fn generate_show(
+    root: &Path,
+    key: &str,
+    seasons: usize,
+    episodes: usize,
+) -> anyhow::Result<PathBuf> {
+    let dir = root.join(key);
+    for season in 1..=seasons {
+        let season_dir = dir.join(format!("Season {}", season));
+        fs::create_dir_all(&season_dir)?;
+        for episode in 1..=episodes {
+            fs::write(
+                season_dir.join(format!("S{:02}E{:04}.mkv", season, episode)),
+                "",
+            )?;
+        }
+    }
+    let next = Path::new(&format!("Season {}", seasons / 2 + 1))
+        .join(format!("S{:02}E0001.mkv", seasons / 2 + 1));
+    let config_path = root.join(format!("{}.toml", key));
+    let mut doc = toml_edit::Document::new();
+    doc["name"] = toml_edit::value(key);
+    doc["directory"] = toml_edit::value(dir.display().to_string());
+    doc["next"] = toml_edit::value(next.display().to_string());
+    fs::write(&config_path, doc.to_string())?;
+    Ok(config_path)
+}
+
+/// Run `f` repeatedly for about a second and print the average time
+/// it took.
+fn bench(name: &str, mut f: impl FnMut()) {
+    let budget = Duration::from_secs(1);
+    let start = Instant::now();
+    let mut iterations = 0u32;
+    while start.elapsed() < budget {
+        f();
+        iterations += 1;
+    }
+    println!(
+        "  {:<30} {:>12.3?} ({} iterations)",
+        name,
+        start.elapsed() / iterations,
+        iterations
+    );
+}
@@ -54,10 +54,13 @@ fn main() -> anyhow::Result<()> {
 
     let app_build = if cargo_bundle {
         // TODO: handle all bundles, not just the macos app one
-        release_dir.join("bundle/osx/pls.app")
+        release_dir
+            .join("bundle/osx")
+            .join(pls::APPLICATION)
+            .with_extension("app")
     } else {
         release_dir
-            .join("pls")
+            .join(pls::APPLICATION)
             .with_extension(&executable_extension)
     };
     let files_to_package = vec![
@@ -67,7 +70,7 @@ fn main() -> anyhow::Result<()> {
     ];
 
     let archive_filename = {
-        let mut s = String::from("pls");
+        let mut s = String::from(pls::APPLICATION);
         if !version.is_empty() {
             s.push('-');
             s.push_str(&version);
@@ -0,0 +1,249 @@
+//! Validation of the config directory, i.e. the `pls check` command.
+
+use crate::{
+    config::{self, CONFIG_KEYS, Config, PROFILE_KEYS, SHOW_KEYS, Version},
+    show::Show,
+    state::State,
+};
+
+use std::{fmt, path::PathBuf};
+
+use toml_edit::TableLike;
+
+/// A mistake found in the config.
+#[derive(Clone, Debug)]
+pub struct Problem {
+    /// The file the mistake is in.
+    pub path: PathBuf,
+    /// Key of the affected show. `None` for problems in `pls.toml`
+    /// itself.
+    pub show: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match &self.show {
+            Some(show) => write!(f, "{} [{}]: {}", self.path.display(), show, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+/// Look for mistakes in the loaded config: a config that still needs
+/// migrating, shows that failed to load, `next` episodes that are
+/// missing or aren't among the show's episodes, unknown keys and
+/// `ordering` entries that match no show.
+///
+/// The `state` should be `read_only` so the migration and the missing
+/// episodes are reported rather than fixed.
+pub fn check(state: &State) -> Vec<Problem> {
+    let mut problems = vec![];
+    let config_problem = |message: String| Problem {
+        path: state.config_path.clone(),
+        show: None,
+        message,
+    };
+
+    if state.config_version < Version::CURRENT {
+        problems.push(config_problem(format!(
+            "The config is for pls {}. It will be migrated to {} the next time pls runs.",
+            state.config_version,
+            Version::CURRENT
+        )));
+    }
+    for key in unknown_keys(state.config.as_table(), CONFIG_KEYS) {
+        problems.push(config_problem(format!("Unknown key `{}`", key)));
+    }
+    let ordering = match Config::from_doc(&state.config) {
+        Ok(config) => config.ordering,
+        Err(error) => {
+            problems.push(config_problem(error.to_string()));
+            vec![]
+        }
+    };
+    for key in ordering {
+        let is_broken = state.broken_shows.iter().any(|broken| broken.key == key);
+        if !state.shows.contains_key(&key) && !is_broken {
+            problems.push(config_problem(format!(
+                "`ordering` lists `{}` which matches no show",
+                key
+            )));
+        }
+    }
+
+    for broken in &state.broken_shows {
+        problems.push(Problem {
+            path: broken.config_path.clone(),
+            show: Some(broken.key.clone()),
+            message: broken.error.to_string(),
+        });
+    }
+
+    for key in &state.ordered_keys {
+        if let Some(show) = state.shows.get(key) {
+            problems.extend(check_show(state, key, show));
+        }
+    }
+    for repair in &state.repairs {
+        if let Some(show) = state.shows.get(&repair.key) {
+            problems.push(Problem {
+                path: show.config_path.clone(),
+                show: Some(repair.key.clone()),
+                message: format!(
+                    "The `next` episode `{}` is gone. It will be replaced with `{}`.",
+                    repair.missing.display(),
+                    repair.next.display()
+                ),
+            });
+        }
+    }
+
+    problems
+}
+
+fn check_show(state: &State, key: &str, show: &Show) -> Vec<Problem> {
+    let mut problems = vec![];
+    let mut problem = |message: String| {
+        problems.push(Problem {
+            path: show.config_path.clone(),
+            show: Some(key.into()),
+            message,
+        })
+    };
+
+    let show_doc;
+    let table = if show.config_path == state.config_path {
+        state
+            .config
+            .get("shows")
+            .and_then(|shows| shows.get(key))
+            .and_then(toml_edit::Item::as_table_like)
+    } else {
+        match config::read_document(&show.config_path) {
+            Ok(doc) => {
+                show_doc = doc;
+                Some(show_doc.as_table() as &dyn TableLike)
+            }
+            Err(error) => {
+                problem(error.to_string());
+                None
+            }
+        }
+    };
+    for unknown in table
+        .map(|t| unknown_keys(t, SHOW_KEYS))
+        .unwrap_or_default()
+    {
+        problem(format!("Unknown key `{}`", unknown));
+    }
+    let profiles = table
+        .and_then(|t| t.get("profiles"))
+        .and_then(toml_edit::Item::as_table_like);
+    for (profile, item) in profiles.iter().flat_map(|profiles| profiles.iter()) {
+        if !state.profiles.iter().any(|p| p == profile) {
+            problem(format!(
+                "Unknown profile `{}`. Add it to `profiles` in `pls.toml`.",
+                profile
+            ));
+        }
+        for unknown in item
+            .as_table_like()
+            .map(|t| unknown_keys(t, PROFILE_KEYS))
+            .unwrap_or_default()
+        {
+            problem(format!(
+                "Unknown key `{}` in profile `{}`",
+                unknown, profile
+            ));
+        }
+    }
+
+    if !show.episodes().contains(&show.current_episode()) {
+        problem(format!(
+            "The `next` episode `{}` is not one of the show's episodes. Check `extensions` and `ignore`.",
+            show.next.display()
+        ));
+    }
+
+    if let Some(playlist_file) = &show.playlist_file
+        && let Err(error) = crate::playlist::read_playlist(playlist_file)
+    {
+        problem(format!(
+            "Could not read the playlist `{}`: {}",
+            playlist_file.display(),
+            error
+        ));
+    }
+
+    for entry in &show.playlist {
+        if !show.dir.join(entry).is_file() {
+            problem(format!(
+                "The `playlist` entry `{}` doesn't exist",
+                entry.display()
+            ));
+        }
+    }
+
+    problems
+}
+
+/// Keys of the `table` that are not in `known`.
+fn unknown_keys(table: &dyn TableLike, known: &[&str]) -> Vec<String> {
+    table
+        .iter()
+        .map(|(key, _)| key)
+        .filter(|key| !known.contains(key))
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::util::test_dir;
+
+    use std::fs;
+
+    /// `pls check` reports what loading the config would change
+    /// without changing it.
+    #[test]
+    fn read_only() {
+        let dir = test_dir("check-read-only");
+        let (config_dir, data_dir) = (dir.join("config"), dir.join("data"));
+        let show_dir = dir.join("show");
+        fs::create_dir_all(&config_dir).unwrap();
+        fs::create_dir_all(&show_dir).unwrap();
+        fs::write(show_dir.join("Episode 2.mkv"), "").unwrap();
+        fs::write(config_dir.join(crate::CONFIG_FILE_NAME), "").unwrap();
+        fs::write(
+            config_dir.join("show.toml"),
+            format!(
+                "name = \"Show\"\ndirectory = {:?}\nnext = \"Episode 1.mkv\"\n",
+                show_dir.display().to_string()
+            ),
+        )
+        .unwrap();
+
+        let state = State::from_config_dir(&config_dir, &data_dir, None, true).unwrap();
+        let problems = check(&state)
+            .iter()
+            .map(|problem| problem.message.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            problems,
+            [
+                "The config is for pls 1.0.0. It will be migrated to 2.0.0 the next time pls runs.",
+                "The `next` episode `Episode 1.mkv` is gone. It will be replaced with `Episode 2.mkv`.",
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(config_dir.join(crate::CONFIG_FILE_NAME)).unwrap(),
+            ""
+        );
+        assert_eq!(fs::read_dir(&config_dir).unwrap().count(), 2);
+        assert!(!data_dir.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
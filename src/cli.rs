@@ -0,0 +1,302 @@
+use pls::{check, history, show::Show, state::State};
+
+use std::path::{Path, PathBuf};
+
+pub const USAGE: &str = "Usage: pls [--profile NAME] [COMMAND]
+
+Run without a command to open the pls window.
+
+Options:
+  --profile NAME         Use the progress and watch history of the given profile
+
+Commands:
+  list                   List all shows, their next episode and unwatched count
+  status [KEY]           Show details of the given show (or all shows)
+  next KEY               Print the path of the next episode
+  play KEY               Play the next episode and advance to the following one
+  prev KEY               Replay the last watched episode
+  set KEY EPISODE        Set the next episode (relative to the show directory)
+  undo KEY               Undo the last change of the next episode
+  restart KEY            Start watching a show again from the first episode
+  history [KEY]          List the recently played episodes of the given show (or all shows)
+  check                  Validate the config and report any problems
+  help                   Print this message
+";
+
+/// Number of the most recent plays printed by the `history` command.
+const HISTORY_LENGTH: usize = 10;
+
+/// The commands handed over to the pls window when it's running, so
+/// it doesn't keep playing from an outdated next episode. The rest
+/// only read the progress and run right here.
+pub const FORWARDED_COMMANDS: &[&str] = &["play", "prev", "set", "undo", "restart"];
+
+/// The commands that load the config without changing anything on
+/// disk (see `State::read_only`).
+pub const READ_ONLY_COMMANDS: &[&str] = &["list", "status", "next", "history", "check"];
+
+/// Whether `args` are one of the commands with the right number of
+/// arguments. Checked before the config is loaded, so `help` and typos
+/// never touch it.
+pub fn is_valid(args: &[String]) -> bool {
+    let Some(command) = args.first() else {
+        return false;
+    };
+    let count = args.len() - 1;
+    match command.as_str() {
+        "list" | "check" => count == 0,
+        "status" | "history" => count <= 1,
+        "next" | "play" | "prev" | "undo" | "restart" => count == 1,
+        "set" => count == 2,
+        _ => false,
+    }
+}
+
+/// Whether `args` ask for the usage.
+pub fn is_help(args: &[String]) -> bool {
+    matches!(
+        args.first().map(String::as_str),
+        Some("help" | "--help" | "-h")
+    )
+}
+
+pub fn run(state: &mut State, args: &[String]) -> anyhow::Result<()> {
+    let command = args.first().map(String::as_str).unwrap_or("help");
+    let params = &args[1..];
+    log::info!("Running command: {command} {params:?}");
+    // NOTE: `check` reports the repairs as problems.
+    if command != "check" {
+        for repair in &state.repairs {
+            eprintln!("{}", repair);
+        }
+    }
+    match (command, params) {
+        ("list", []) => list(state),
+        ("status", []) => {
+            for key in &state.ordered_keys {
+                if let Some(show) = state.shows.get(key) {
+                    status(state, key, show);
+                }
+            }
+            print_broken_shows(state);
+        }
+        ("status", [key]) => status(state, key, show(state, key)?),
+        ("next", [key]) => {
+            let show = show(state, key)?;
+            if show.finished {
+                anyhow::bail!("The show `{key}` is finished");
+            }
+            println!("{}", show.current_episode().display());
+        }
+        ("play", [key]) => play(state, key)?,
+        ("prev", [key]) => {
+            let episode = state.replay_previous_episode(key)?;
+            println!("Replaying: {}", episode.display());
+        }
+        ("history", []) => {
+            let plays = history::read(&state.profile_dir())?;
+            for key in &state.ordered_keys {
+                if state.shows.contains_key(key) {
+                    print_history(&plays, key);
+                }
+            }
+        }
+        ("history", [key]) => {
+            show(state, key)?;
+            print_history(&history::read(&state.profile_dir())?, key);
+        }
+        ("set", [key, episode]) => set(state, key, episode)?,
+        ("restart", [key]) => {
+            show(state, key)?;
+            state.restart_show(key)?;
+            println!("Next: {}", show(state, key)?.next.display());
+        }
+        ("undo", [key]) => {
+            show(state, key)?;
+            state.undo_last_advance(key)?;
+            println!("Next: {}", show(state, key)?.next.display());
+        }
+        ("check", []) => check(state)?,
+        ("help" | "--help" | "-h", _) => print!("{}", USAGE),
+        _ => {
+            eprint!("{}", USAGE);
+            anyhow::bail!("Unknown command or wrong arguments: {}", args.join(" "));
+        }
+    }
+    Ok(())
+}
+
+/// Run a command forwarded from another launch of pls in the window.
+/// Returns what's printed by that launch. Players are left running in
+/// the window, `on_exit` is called once they exit.
+pub fn run_forwarded(
+    state: &mut State,
+    args: &[String],
+    on_exit: impl FnOnce() + Send + 'static,
+) -> anyhow::Result<String> {
+    log::info!("Running forwarded command: {:?}", args);
+    match args {
+        // NOTE: epi can't bring the window to the front, so it tells
+        // the user where the launch went instead.
+        [] => {
+            state.notice = Some("pls was launched again while this window was open.".into());
+            Ok("pls is already open in another window".into())
+        }
+        [command, key] if command == "play" => {
+            let episode = show(state, key)?.current_episode();
+            state.play_next_episode(key, on_exit)?;
+            Ok(format!("Playing: {}", episode.display()))
+        }
+        [command, key] if command == "prev" => {
+            let episode = state.replay_previous_episode(key)?;
+            Ok(format!("Replaying: {}", episode.display()))
+        }
+        [command, key, episode] if command == "set" => {
+            let episode = show(state, key)?.dir.join(PathBuf::from(episode));
+            state.set_next_episode(key, &episode)?;
+            Ok(format!("Next: {}", show(state, key)?.next.display()))
+        }
+        [command, key] if command == "undo" || command == "restart" => {
+            show(state, key)?;
+            if command == "undo" {
+                state.undo_last_advance(key)?;
+            } else {
+                state.restart_show(key)?;
+            }
+            Ok(format!("Next: {}", show(state, key)?.next.display()))
+        }
+        _ => anyhow::bail!("Unknown command or wrong arguments: {}", args.join(" ")),
+    }
+}
+
+fn show<'a>(state: &'a State, key: &str) -> anyhow::Result<&'a Show> {
+    state.shows.get(key).ok_or_else(|| unknown_show(state, key))
+}
+
+/// The error for a `key` that's not among the loaded shows. Explains
+/// why if the show failed to load.
+fn unknown_show(state: &State, key: &str) -> anyhow::Error {
+    match state.broken_shows.iter().find(|broken| broken.key == key) {
+        Some(broken) => anyhow::anyhow!(
+            "Could not load show `{key}` from {}:\n{}",
+            broken.config_path.display(),
+            broken.error
+        ),
+        None => anyhow::anyhow!("Unknown show: `{key}`"),
+    }
+}
+
+fn list(state: &State) {
+    for key in &state.ordered_keys {
+        if let Some(show) = state.shows.get(key) {
+            let finished = if show.finished { " (finished)" } else { "" };
+            println!(
+                "{}\t{}\t{}{}\t{}",
+                key,
+                show.name,
+                show.next.display(),
+                finished,
+                show.unwatched_count()
+            );
+        }
+    }
+    print_broken_shows(state);
+}
+
+/// List the shows that failed to load (on stderr so the output of
+/// `list` stays easy to process).
+fn print_broken_shows(state: &State) {
+    if state.broken_shows.is_empty() {
+        return;
+    }
+    eprintln!("\nBroken shows:");
+    for broken in &state.broken_shows {
+        eprintln!("{} ({}):", broken.key, broken.config_path.display());
+        for line in broken.error.to_string().lines() {
+            eprintln!("  {}", line);
+        }
+    }
+}
+
+fn status(state: &State, key: &str, show: &Show) {
+    let number = |episode: &Path| {
+        show.episode_number(episode)
+            .map(|number| format!(" ({})", number))
+            .unwrap_or_default()
+    };
+    println!("{} ({})", show.name, key);
+    println!("  Location: {}", show.location().display());
+    let current = show.current_episode();
+    if show.finished {
+        println!("  Finished: {}{}", show.next.display(), number(&current));
+    } else {
+        println!("  Next:     {}{}", show.next.display(), number(&current));
+    }
+    if let Some(previous) = show.previous_episode() {
+        println!("  Previous: {}{}", previous.display(), number(&previous));
+    }
+    println!("  Unwatched: {}", show.unwatched_count());
+    if let Some(new_episodes) = state.new_episodes.get(key) {
+        println!("  New since the last run:");
+        for episode in new_episodes {
+            println!("    {}", episode.display());
+        }
+    }
+}
+
+fn print_history(plays: &[history::Play], key: &str) {
+    println!("{}:", key);
+    for play in history::recent(plays, key, HISTORY_LENGTH) {
+        println!(
+            "  {}  {}  ({})",
+            play.timestamp_str(),
+            play.episode.display(),
+            play.hostname
+        );
+    }
+}
+
+fn play(state: &mut State, key: &str) -> anyhow::Result<()> {
+    let current_episode = show(state, key)?.current_episode();
+    println!("Playing: {}", current_episode.display());
+    state.play_next_episode(key, || {})?;
+    state.wait_for_playbacks()?;
+    let show = show(state, key)?;
+    if show.finished {
+        println!("Finished the show. Run `pls restart {key}` to watch it again.");
+    } else if show.current_episode() == current_episode {
+        println!("The episode wasn't watched. Not advancing.");
+        if let Some(position) = show.position {
+            println!("Resume at: {:.0}s", position);
+        }
+    } else {
+        println!("Next:    {}", show.next.display());
+    }
+    Ok(())
+}
+
+fn set(state: &mut State, key: &str, episode: &str) -> anyhow::Result<()> {
+    let episode = show(state, key)?.dir.join(PathBuf::from(episode));
+    state.set_next_episode(key, &episode)?;
+    println!("Next: {}", show(state, key)?.next.display());
+    Ok(())
+}
+
+fn check(state: &State) -> anyhow::Result<()> {
+    let problems = check::check(state);
+    if problems.is_empty() {
+        println!("OK: {} shows, no problems found", state.shows.len());
+        return Ok(());
+    }
+    for problem in &problems {
+        let message = problem.to_string();
+        let mut lines = message.lines();
+        if let Some(first) = lines.next() {
+            println!("{}", first);
+        }
+        for line in lines {
+            println!("  {}", line);
+        }
+    }
+    anyhow::bail!("Found {} problem(s) in the config", problems.len())
+}
@@ -0,0 +1,747 @@
+use crate::{history, progress, show::Sort, snapshot, storage};
+
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use toml_edit::{Document, Item, Table, TableLike};
+
+/// Version of the config file format.
+///
+/// * 1.0.0: every show is in its own `<key>.toml` file, per-host
+///   directories are `directory_<hostname>` keys.
+/// * 2.0.0: shows can also be defined inline in `pls.toml` as
+///   `[shows.<key>]` tables, per-host directories (and playlists) are
+///   `[directories]` (and `[playlists]`) tables keyed by the hostname.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Version {
+    V1_0_0,
+    V2_0_0,
+}
+
+impl Version {
+    /// The version written by this pls. Older configs are migrated to
+    /// it on load.
+    pub const CURRENT: Version = Version::V2_0_0;
+
+    /// Configs without a `version` predate versioning, i.e. they're
+    /// 1.0.0.
+    pub fn fallback() -> Self {
+        Version::V1_0_0
+    }
+}
+
+impl std::str::FromStr for Version {
+    type Err = ConfigError;
+
+    fn from_str(version_str: &str) -> Result<Self, Self::Err> {
+        match version_str {
+            "1.0.0" => Ok(Version::V1_0_0),
+            "2.0.0" => Ok(Version::V2_0_0),
+            _ => Err(ConfigError::UnsupportedVersion(version_str.into())),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        use Version::*;
+        let s = match self {
+            V1_0_0 => "1.0.0",
+            V2_0_0 => "2.0.0",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Why the config (or a show defined in it) couldn't be loaded.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file couldn't be read.
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// The config file isn't valid TOML.
+    Parse {
+        path: PathBuf,
+        line: Option<usize>,
+        column: Option<usize>,
+        message: String,
+    },
+    /// The `version` is newer than this pls understands.
+    UnsupportedVersion(String),
+    /// The value under `key` has the wrong type or is invalid.
+    InvalidValue {
+        key: String,
+        message: String,
+    },
+    /// None of `directory`, `directories`, `playlist` or `playlists`
+    /// is set (for this computer).
+    MissingLocation {
+        hostname: Option<String>,
+    },
+    NonexistentDirectory(PathBuf),
+    NonexistentPlaylist(PathBuf),
+    /// The playlist file isn't an M3U/M3U8 or PLS file.
+    UnsupportedPlaylist(PathBuf),
+    /// There's no `next` to play and no episode to fall back to.
+    NoEpisodes(PathBuf),
+    /// `next` doesn't point to an existing episode.
+    MissingNext {
+        next: PathBuf,
+        dir: PathBuf,
+    },
+    /// `next` exists but isn't one of the show's episodes (i.e. its
+    /// `extensions` or `ignore` leave it out).
+    NextFiltered {
+        next: PathBuf,
+        dir: PathBuf,
+    },
+}
+
+impl ConfigError {
+    /// A TOML parse error of the file at `path`. `TomlError` only
+    /// exposes the position in its message, so it's parsed from there.
+    pub fn parse(path: &Path, error: toml_edit::TomlError) -> Self {
+        let message = error.to_string();
+        let position = message
+            .lines()
+            .next()
+            .and_then(|line| line.split_once("at line "))
+            .and_then(|(_, position)| position.split_once(", column "))
+            .and_then(|(line, column)| Some((line.parse().ok()?, column.parse().ok()?)));
+        ConfigError::Parse {
+            path: path.into(),
+            line: position.map(|(line, _)| line),
+            column: position.map(|(_, column)| column),
+            message: match position {
+                Some(_) => message.lines().skip(1).collect::<Vec<_>>().join("\n"),
+                None => message,
+            },
+        }
+    }
+
+    fn invalid(key: &str, message: impl fmt::Display) -> Self {
+        ConfigError::InvalidValue {
+            key: key.into(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        use ConfigError::*;
+        match self {
+            Io { path, error } => write!(f, "Could not read `{}`: {}", path.display(), error),
+            Parse {
+                path,
+                line: Some(line),
+                column: Some(column),
+                message,
+            } => write!(
+                f,
+                "Invalid TOML in `{}` at line {}, column {}:\n{}",
+                path.display(),
+                line,
+                column,
+                message
+            ),
+            Parse { path, message, .. } => {
+                write!(f, "Invalid TOML in `{}`:\n{}", path.display(), message)
+            }
+            UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported config version: `{}`. This version of pls supports versions up to {}. Is there a newer pls available?",
+                version,
+                Version::CURRENT
+            ),
+            InvalidValue { key, message } => write!(f, "Invalid value for `{}`: {}", key, message),
+            MissingLocation { hostname } => {
+                let hostname = hostname.as_deref().unwrap_or("hostname");
+                write!(
+                    f,
+                    "None of the `directory`, `directories.{0}`, `playlist` or `playlists.{0}` keys are set",
+                    hostname
+                )
+            }
+            NonexistentDirectory(dir) => {
+                write!(f, "The directory `{}` doesn't exist", dir.display())
+            }
+            NonexistentPlaylist(playlist) => {
+                write!(
+                    f,
+                    "The playlist file `{}` doesn't exist",
+                    playlist.display()
+                )
+            }
+            UnsupportedPlaylist(playlist) => write!(
+                f,
+                "Unsupported playlist file `{}`. Expected an `.m3u`, `.m3u8` or `.pls` file",
+                playlist.display()
+            ),
+            NoEpisodes(location) => write!(
+                f,
+                "No `next` episode specified and there are no episodes in `{}`",
+                location.display()
+            ),
+            MissingNext { next, dir } => write!(
+                f,
+                "The `next` episode `{}` doesn't exist in `{}`",
+                next.display(),
+                dir.display()
+            ),
+            NextFiltered { next, dir } => write!(
+                f,
+                "The `next` episode `{}` in `{}` is not one of the show's episodes. Check `extensions` and `ignore`.",
+                next.display(),
+                dir.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Read and parse the TOML file at `path`.
+pub fn read_document(path: &Path) -> Result<Document, ConfigError> {
+    let toml = fs::read_to_string(path).map_err(|error| ConfigError::Io {
+        path: path.into(),
+        error,
+    })?;
+    toml.parse::<Document>()
+        .map_err(|error| ConfigError::parse(path, error))
+}
+
+/// Return the version of the `pls.toml` document.
+pub fn version(doc: &Document) -> Result<Version, ConfigError> {
+    match get_str(doc.as_table(), "version")? {
+        Some(version) => version.parse(),
+        None => {
+            let fallback = Version::fallback();
+            log::warn!("No config version specified. Assuming: {}", fallback);
+            Ok(fallback)
+        }
+    }
+}
+
+/// All the keys `pls.toml` understands.
+pub const CONFIG_KEYS: &[&str] = &[
+    "version",
+    "ordering",
+    "player",
+    "min_watched",
+    "mpv_ipc",
+    "watched_percent",
+    "profiles",
+    "shows",
+];
+
+/// All the keys a show definition understands.
+pub const SHOW_KEYS: &[&str] = &[
+    "name",
+    "directory",
+    "directories",
+    "playlist",
+    "playlists",
+    "next",
+    "position",
+    "finished",
+    "undo",
+    "extensions",
+    "ignore",
+    "sort",
+    "player",
+    "min_watched",
+    "mpv_ipc",
+    "watched_percent",
+    "profiles",
+];
+
+/// All the keys a `[profiles.<name>]` table of a show understands.
+pub const PROFILE_KEYS: &[&str] = &["next", "position", "finished", "undo"];
+
+/// The typed contents of `pls.toml` (apart from the inline shows).
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    pub ordering: Vec<String>,
+    /// Names of the people sharing the shows. Each one has their own
+    /// progress and watch history.
+    pub profiles: Vec<String>,
+    pub player: Option<Vec<String>>,
+    pub min_watched: Option<Duration>,
+    pub mpv_ipc: Option<bool>,
+    pub watched_percent: Option<f64>,
+}
+
+impl Config {
+    pub fn from_doc(doc: &Document) -> Result<Self, ConfigError> {
+        let table = doc.as_table();
+        Ok(Config {
+            ordering: get_str_array(table, "ordering")?.unwrap_or_default(),
+            profiles: get_profiles(table)?,
+            player: get_str_array(table, "player")?,
+            min_watched: get_seconds(table, "min_watched")?,
+            mpv_ipc: get_bool(table, "mpv_ipc")?,
+            watched_percent: get_float(table, "watched_percent")?,
+        })
+    }
+}
+
+/// The typed contents of a show definition: either a `<key>.toml`
+/// file or a `[shows.<key>]` table in `pls.toml`.
+#[derive(Clone, Debug, Default)]
+pub struct ShowConfig {
+    pub name: Option<String>,
+    pub directory: Option<String>,
+    /// Per-host directories keyed by the hostname.
+    pub directories: HashMap<String, String>,
+    /// `playlist` set to the path of a playlist file.
+    pub playlist_file: Option<String>,
+    /// Per-host playlist files keyed by the hostname.
+    pub playlists: HashMap<String, String>,
+    /// `playlist` set to a list of episodes.
+    pub playlist: Vec<String>,
+    pub next: Option<String>,
+    pub position: Option<f64>,
+    pub finished: bool,
+    /// The earlier `next` episodes, most recent last.
+    pub undo: Vec<String>,
+    pub extensions: Option<Vec<String>>,
+    pub ignore: Vec<String>,
+    pub sort: Option<Sort>,
+    pub player: Option<Vec<String>>,
+    pub min_watched: Option<Duration>,
+    pub mpv_ipc: Option<bool>,
+    pub watched_percent: Option<f64>,
+}
+
+impl ShowConfig {
+    pub fn from_table(table: &dyn TableLike) -> Result<Self, ConfigError> {
+        let (playlist_file, playlist) = match table.get("playlist") {
+            Some(item) if item.is_str() => (get_str(table, "playlist")?, vec![]),
+            _ => (None, get_str_array(table, "playlist")?.unwrap_or_default()),
+        };
+        Ok(ShowConfig {
+            name: get_str(table, "name")?,
+            directory: get_str(table, "directory")?,
+            directories: get_str_table(table, "directories")?,
+            playlist_file,
+            playlists: get_str_table(table, "playlists")?,
+            playlist,
+            next: get_str(table, "next")?,
+            position: get_float(table, "position")?,
+            finished: get_bool(table, "finished")?.unwrap_or(false),
+            undo: get_str_array(table, "undo")?.unwrap_or_default(),
+            extensions: get_str_array(table, "extensions")?,
+            ignore: get_str_array(table, "ignore")?.unwrap_or_default(),
+            sort: get_str(table, "sort")?
+                .map(|sort| sort.parse())
+                .transpose()
+                .map_err(|error| ConfigError::invalid("sort", error))?,
+            player: get_str_array(table, "player")?,
+            min_watched: get_seconds(table, "min_watched")?,
+            mpv_ipc: get_bool(table, "mpv_ipc")?,
+            watched_percent: get_float(table, "watched_percent")?,
+        })
+    }
+
+    /// Use the progress (`next`, `position`, `finished` and `undo`) of
+    /// the given `profile`, i.e. the `[profiles.<profile>]` table of
+    /// the show, instead of the show's own. A profile that hasn't
+    /// watched the show yet starts at the first episode. `None` keeps
+    /// the show's own progress.
+    pub fn with_profile(
+        self,
+        table: &dyn TableLike,
+        profile: Option<&str>,
+    ) -> Result<Self, ConfigError> {
+        let Some(profile) = profile else {
+            return Ok(self);
+        };
+        let empty = Table::new();
+        let table = match table.get("profiles").and_then(|p| p.get(profile)) {
+            Some(item) => item
+                .as_table_like()
+                .ok_or_else(|| type_error(&format!("profiles.{profile}"), "a table"))?,
+            None => &empty,
+        };
+        self.with_progress(Some(table))
+    }
+
+    /// Use the progress saved in the progress file (see
+    /// [`crate::progress`]) instead of the one in the show config.
+    /// `None` (nothing saved yet) keeps the show config's.
+    pub fn with_progress(mut self, table: Option<&dyn TableLike>) -> Result<Self, ConfigError> {
+        let Some(table) = table else {
+            return Ok(self);
+        };
+        self.next = get_str(table, "next")?;
+        self.position = get_float(table, "position")?;
+        self.finished = get_bool(table, "finished")?.unwrap_or(false);
+        self.undo = get_str_array(table, "undo")?.unwrap_or_default();
+        Ok(self)
+    }
+
+    /// The directory or playlist file the show's episodes are in on
+    /// the computer with the given `hostname`. The host-specific
+    /// entries take precedence and the directory wins if both are
+    /// specified at the same level.
+    pub fn location<'a>(&'a self, hostname: Option<&str>) -> Option<Location<'a>> {
+        let host_entry = |entries: &'a HashMap<String, String>| {
+            hostname.and_then(|hostname| entries.get(hostname).map(String::as_str))
+        };
+        host_entry(&self.directories)
+            .map(Location::Directory)
+            .or_else(|| host_entry(&self.playlists).map(Location::Playlist))
+            .or_else(|| self.directory.as_deref().map(Location::Directory))
+            .or_else(|| self.playlist_file.as_deref().map(Location::Playlist))
+    }
+}
+
+/// Where a show's episodes come from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Location<'a> {
+    Directory(&'a str),
+    Playlist(&'a str),
+}
+
+/// Upgrade the config directory from the version `from` to
+/// `Version::CURRENT`.
+///
+/// All the TOML files are backed up into a `backup-<version>`
+/// directory first. The lock is taken in `data_dir`, so nothing but
+/// the config and its backups ends up in the config directory.
+pub fn migrate(config_dir: &Path, data_dir: &Path, from: Version) -> anyhow::Result<()> {
+    if from >= Version::CURRENT {
+        return Ok(());
+    }
+    log::info!(
+        "Migrating the config in {} from {} to {}",
+        config_dir.display(),
+        from,
+        Version::CURRENT
+    );
+    let _lock = storage::lock(data_dir)?;
+    let backup_dir = backup(config_dir, from)?;
+    log::info!("Backed up the config to: {}", backup_dir.display());
+
+    if from == Version::V1_0_0 {
+        for path in toml_files(config_dir)? {
+            if !is_show_file(&path) {
+                continue;
+            }
+            let result = read_document(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|mut doc| {
+                    migrate_show_v1_to_v2(doc.as_table_mut());
+                    Ok(storage::write(&path, doc.to_string())?)
+                });
+            if let Err(error) = result {
+                log::error!("Could not migrate show {}: {}", path.display(), error);
+            }
+        }
+    }
+
+    let config_path = config_dir.join(crate::CONFIG_FILE_NAME);
+    let mut doc = read_document(&config_path)?;
+    doc["version"] = toml_edit::value(Version::CURRENT.to_string());
+    storage::write(&config_path, doc.to_string())?;
+    Ok(())
+}
+
+/// Move the `directory_<hostname>` and `playlist_<hostname>` keys
+/// into the `directories` and `playlists` tables.
+fn migrate_show_v1_to_v2(table: &mut dyn TableLike) {
+    for (prefix, table_key) in [("directory_", "directories"), ("playlist_", "playlists")] {
+        let keys = table
+            .iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .map(|(key, _)| key.to_string())
+            .collect::<Vec<_>>();
+        for key in keys {
+            let hostname = &key[prefix.len()..];
+            if let Some(item) = table.remove(&key) {
+                if !table.contains_key(table_key) {
+                    table.insert(table_key, toml_edit::table());
+                }
+                if let Some(entries) = table.get_mut(table_key).and_then(Item::as_table_like_mut) {
+                    entries.insert(hostname, item);
+                }
+            }
+        }
+    }
+}
+
+/// Copy all the TOML files in `config_dir` into a new backup directory.
+fn backup(config_dir: &Path, version: Version) -> anyhow::Result<PathBuf> {
+    let mut backup_dir = config_dir.join(format!("backup-{}", version));
+    let mut suffix = 1;
+    while backup_dir.exists() {
+        suffix += 1;
+        backup_dir = config_dir.join(format!("backup-{}-{}", version, suffix));
+    }
+    fs::create_dir(&backup_dir)?;
+    for path in toml_files(config_dir)? {
+        if let Some(file_name) = path.file_name() {
+            fs::copy(&path, backup_dir.join(file_name))?;
+        }
+    }
+    Ok(backup_dir)
+}
+
+/// Set `key` to `value`, keeping the formatting and comments around
+/// the existing value (if any).
+pub fn set_value(table: &mut dyn TableLike, key: &str, value: impl Into<toml_edit::Value>) {
+    let mut value = value.into();
+    match table.get_mut(key).and_then(Item::as_value_mut) {
+        Some(existing) => {
+            *value.decor_mut() = existing.decor().clone();
+            *existing = value;
+        }
+        None => {
+            table.insert(key, Item::Value(value));
+        }
+    }
+}
+
+/// Whether the TOML file at `path` (in the config directory) can be
+/// a show config. `pls.toml` and the files pls keeps its own data in
+/// can't (the data directory can be the config directory, e.g. on
+/// macOS).
+pub fn is_show_file(path: &Path) -> bool {
+    let reserved = [
+        crate::CONFIG_FILE_NAME,
+        history::HISTORY_FILE_NAME,
+        snapshot::SNAPSHOT_FILE_NAME,
+        progress::PROGRESS_FILE_NAME,
+    ];
+    !path
+        .file_name()
+        .is_some_and(|file_name| reserved.iter().any(|r| file_name == OsStr::new(r)))
+}
+
+/// All the `.toml` files directly in `dir`.
+pub fn toml_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut result = vec![];
+    for entry in dir.read_dir()? {
+        let path = entry?.path();
+        if path.is_file() && path.extension() == Some(OsStr::new("toml")) {
+            result.push(path);
+        }
+    }
+    result.sort();
+    Ok(result)
+}
+
+/// The `profiles` list. The names are used as directory names, so
+/// they're limited to letters, numbers, `-` and `_`.
+fn get_profiles(table: &dyn TableLike) -> Result<Vec<String>, ConfigError> {
+    let profiles = get_str_array(table, "profiles")?.unwrap_or_default();
+    for profile in &profiles {
+        let is_valid = !profile.is_empty()
+            && profile
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+        if !is_valid {
+            return Err(ConfigError::invalid(
+                "profiles",
+                format!("`{profile}` can only contain letters, numbers, `-` and `_`"),
+            ));
+        }
+    }
+    Ok(profiles)
+}
+
+fn type_error(key: &str, expected: &str) -> ConfigError {
+    ConfigError::invalid(key, format!("expected {expected}"))
+}
+
+fn get_str(table: &dyn TableLike, key: &str) -> Result<Option<String>, ConfigError> {
+    table
+        .get(key)
+        .map(|item| {
+            item.as_str()
+                .map(String::from)
+                .ok_or_else(|| type_error(key, "a string"))
+        })
+        .transpose()
+}
+
+fn get_bool(table: &dyn TableLike, key: &str) -> Result<Option<bool>, ConfigError> {
+    table
+        .get(key)
+        .map(|item| {
+            item.as_bool()
+                .ok_or_else(|| type_error(key, "true or false"))
+        })
+        .transpose()
+}
+
+/// A number, whether it's written as an integer or a float.
+fn get_float(table: &dyn TableLike, key: &str) -> Result<Option<f64>, ConfigError> {
+    table
+        .get(key)
+        .map(|item| {
+            item.as_float()
+                .or_else(|| item.as_integer().map(|i| i as f64))
+                .ok_or_else(|| type_error(key, "a number"))
+        })
+        .transpose()
+}
+
+/// A non-negative integer number of seconds.
+fn get_seconds(table: &dyn TableLike, key: &str) -> Result<Option<Duration>, ConfigError> {
+    table
+        .get(key)
+        .map(|item| {
+            item.as_integer()
+                .and_then(|seconds| u64::try_from(seconds).ok())
+                .map(Duration::from_secs)
+                .ok_or_else(|| type_error(key, "a number of seconds"))
+        })
+        .transpose()
+}
+
+fn get_str_array(table: &dyn TableLike, key: &str) -> Result<Option<Vec<String>>, ConfigError> {
+    table
+        .get(key)
+        .map(|item| {
+            item.as_array()
+                .and_then(|a| {
+                    a.iter()
+                        .map(|i| i.as_str().map(String::from))
+                        .collect::<Option<Vec<_>>>()
+                })
+                .ok_or_else(|| type_error(key, "a list of strings"))
+        })
+        .transpose()
+}
+
+fn get_str_table(table: &dyn TableLike, key: &str) -> Result<HashMap<String, String>, ConfigError> {
+    let Some(item) = table.get(key) else {
+        return Ok(HashMap::new());
+    };
+    let entries = item
+        .as_table_like()
+        .ok_or_else(|| type_error(key, "a table"))?;
+    entries
+        .iter()
+        .map(|(entry_key, value)| {
+            value
+                .as_str()
+                .map(|value| (entry_key.to_string(), value.to_string()))
+                .ok_or_else(|| type_error(&format!("{key}.{entry_key}"), "a string"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn show_v1_to_v2() {
+        let mut doc = "name = \"Show\"\n\
+            directory_laptop = \"/home/me/Show\"\n\
+            directory_desktop = \"D:\\\\Show\"\n\
+            playlist_tv = \"/media/show.m3u\"\n\
+            next = \"Episode 1.mkv\"\n"
+            .parse::<Document>()
+            .unwrap();
+        migrate_show_v1_to_v2(doc.as_table_mut());
+
+        let config = ShowConfig::from_table(doc.as_table()).unwrap();
+        assert_eq!(config.directories.len(), 2);
+        assert_eq!(config.directories["laptop"], "/home/me/Show");
+        assert_eq!(config.directories["desktop"], "D:\\Show");
+        assert_eq!(config.playlists.len(), 1);
+        assert_eq!(config.playlists["tv"], "/media/show.m3u");
+        assert_eq!(
+            config.location(Some("tv")),
+            Some(Location::Playlist("/media/show.m3u"))
+        );
+        for key in ["directory_laptop", "directory_desktop", "playlist_tv"] {
+            assert!(!doc.contains_key(key), "{key}");
+        }
+        assert_eq!(doc["name"].as_str(), Some("Show"));
+        assert_eq!(doc["next"].as_str(), Some("Episode 1.mkv"));
+    }
+
+    #[test]
+    fn migrate_config_dir() {
+        let dir = crate::util::test_dir("migrate");
+        let data_dir = dir.join("data");
+        let show = "name = \"Show\"\ndirectory_laptop = \"/home/me/Show\"\n";
+        fs::write(dir.join(crate::CONFIG_FILE_NAME), "ordering = [\"show\"]\n").unwrap();
+        fs::write(dir.join("show.toml"), show).unwrap();
+
+        migrate(&dir, &data_dir, Version::V1_0_0).unwrap();
+        let backup_dir = dir.join("backup-1.0.0");
+        assert_eq!(
+            fs::read_to_string(backup_dir.join("show.toml")).unwrap(),
+            show
+        );
+        let doc = read_document(&dir.join(crate::CONFIG_FILE_NAME)).unwrap();
+        assert_eq!(version(&doc).unwrap(), Version::CURRENT);
+        let doc = read_document(&dir.join("show.toml")).unwrap();
+        assert_eq!(doc["directories"]["laptop"].as_str(), Some("/home/me/Show"));
+        assert!(!dir.join(storage::LOCK_FILE_NAME).exists());
+
+        // NOTE: an existing backup is never overwritten.
+        migrate(&dir, &data_dir, Version::V1_0_0).unwrap();
+        migrate(&dir, &data_dir, Version::V1_0_0).unwrap();
+        assert!(dir.join("backup-1.0.0-2").is_dir());
+        assert!(dir.join("backup-1.0.0-3").is_dir());
+        assert_eq!(
+            fs::read_to_string(backup_dir.join("show.toml")).unwrap(),
+            show
+        );
+
+        // Nothing to do for a current config.
+        migrate(&dir, &data_dir, Version::CURRENT).unwrap();
+        assert!(!dir.join("backup-2.0.0").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reserved_files() {
+        assert!(is_show_file(Path::new("/config/show.toml")));
+        for reserved in [
+            "pls.toml",
+            "history.toml",
+            "known_episodes.toml",
+            "progress.toml",
+        ] {
+            assert!(
+                !is_show_file(&Path::new("/config").join(reserved)),
+                "{reserved}"
+            );
+        }
+    }
+
+    #[test]
+    fn versions() {
+        let doc = "version = \"1.0.0\"".parse::<Document>().unwrap();
+        assert_eq!(version(&doc).unwrap(), Version::V1_0_0);
+        let doc = Document::new();
+        assert_eq!(version(&doc).unwrap(), Version::fallback());
+        let doc = "version = \"3.0.0\"".parse::<Document>().unwrap();
+        assert!(matches!(
+            version(&doc),
+            Err(ConfigError::UnsupportedVersion(version)) if version == "3.0.0"
+        ));
+    }
+}
@@ -0,0 +1,339 @@
+//! Creating, editing and deleting shows from the window.
+//!
+//! All the changes are written through `toml_edit`, so any comments
+//! and formatting in the existing config files are kept.
+
+use crate::{
+    config, progress,
+    state::State,
+    storage::{self, Fingerprint},
+};
+
+use std::{fs, path::Path};
+
+use toml_edit::{Document, Item, TableLike};
+
+/// The contents of the show editor dialog.
+#[derive(Clone, Debug, Default)]
+pub struct ShowEditor {
+    /// Key of the show being edited. `None` when creating a new show.
+    pub editing: Option<String>,
+    pub key: String,
+    pub name: String,
+    pub directory: String,
+    /// The directory on this computer (`directories.<hostname>`).
+    pub host_directory: String,
+    /// The episode to play next, relative to the directory. Empty
+    /// keeps the current one (the first episode for new shows).
+    pub next: String,
+    /// The Delete button was clicked once and needs a confirmation.
+    pub confirm_delete: bool,
+    /// The show's config file as it was when the editor was opened.
+    /// Saving is refused if it changed since.
+    pub fingerprint: Fingerprint,
+}
+
+impl ShowEditor {
+    /// An empty editor for creating a new show.
+    pub fn new_show() -> Self {
+        ShowEditor::default()
+    }
+
+    /// An editor filled with the current values of the show with the
+    /// given `key`.
+    pub fn edit_show(state: &State, key: &str) -> anyhow::Result<Self> {
+        let show = state
+            .shows
+            .get(key)
+            .ok_or_else(|| anyhow::anyhow!("Unknown show: `{key}`"))?;
+        let mut doc = config::read_document(&show.config_path)?;
+        let table = show_table(&mut doc, key, show.config_path == state.config_path)?;
+        let string = |key: &str| {
+            table
+                .get(key)
+                .and_then(Item::as_str)
+                .unwrap_or_default()
+                .to_string()
+        };
+        let host_directory = crate::util::hostname()
+            .and_then(|hostname| {
+                table
+                    .get("directories")
+                    .and_then(|directories| directories.get(&hostname))
+                    .and_then(Item::as_str)
+                    .map(String::from)
+            })
+            .unwrap_or_default();
+        Ok(ShowEditor {
+            editing: Some(key.into()),
+            key: key.into(),
+            name: string("name"),
+            directory: string("directory"),
+            host_directory,
+            next: show.next.display().to_string(),
+            confirm_delete: false,
+            fingerprint: Fingerprint::of(&show.config_path)?,
+        })
+    }
+
+    /// Write the show into its config file (a new `<key>.toml` file
+    /// for new shows) and reload the config.
+    pub fn save(&self, state: &mut State) -> anyhow::Result<()> {
+        let key = self.key.trim();
+        let name = self.name.trim();
+        let directory = self.directory.trim();
+        let host_directory = self.host_directory.trim();
+        let next = self.next.trim();
+
+        // NOTE: shows played from a playlist file don't need a
+        // directory. The `next` episode is relative to the playlist.
+        let (path, mut doc, inline, playlist_dir) = match &self.editing {
+            Some(key) => {
+                let show = state
+                    .shows
+                    .get(key)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown show: `{key}`"))?;
+                let doc = config::read_document(&show.config_path)?;
+                let inline = show.config_path == state.config_path;
+                let playlist_dir = show.playlist_file.as_ref().map(|_| show.dir.clone());
+                (show.config_path.clone(), doc, inline, playlist_dir)
+            }
+            None => {
+                validate_key(state, key)?;
+                let path = state.config_dir().join(format!("{}.toml", key));
+                (path, Document::new(), false, None)
+            }
+        };
+
+        if name.is_empty() {
+            anyhow::bail!("The show needs a name");
+        }
+        if directory.is_empty() && host_directory.is_empty() && playlist_dir.is_none() {
+            anyhow::bail!("The show needs a directory");
+        }
+        for dir in [directory, host_directory] {
+            if !dir.is_empty() && !Path::new(dir).is_dir() {
+                anyhow::bail!("The directory `{}` doesn't exist", dir);
+            }
+        }
+        let dir = match (host_directory, directory, playlist_dir) {
+            (_, _, Some(playlist_dir)) => playlist_dir,
+            ("", directory, None) => directory.into(),
+            (host_directory, _, None) => host_directory.into(),
+        };
+        if !next.is_empty() && !dir.join(next).is_file() {
+            anyhow::bail!(
+                "The episode `{}` doesn't exist in `{}`",
+                next,
+                dir.display()
+            );
+        }
+
+        log::info!("Saving show `{}` into {}", key, path.display());
+
+        let table = show_table(&mut doc, key, inline)?;
+        config::set_value(table, "name", name);
+        set_or_remove(table, "directory", directory);
+        if let Some(hostname) = crate::util::hostname() {
+            if !table.contains_key("directories") && !host_directory.is_empty() {
+                table.insert("directories", toml_edit::table());
+            }
+            if let Some(directories) = table
+                .get_mut("directories")
+                .and_then(Item::as_table_like_mut)
+            {
+                set_or_remove(directories, &hostname, host_directory);
+                if directories.is_empty() {
+                    table.remove("directories");
+                }
+            }
+        } else if !host_directory.is_empty() {
+            anyhow::bail!("Could not determine the name of this computer");
+        }
+
+        {
+            let _lock = storage::lock(&state.data_dir)?;
+            let changed = match &self.editing {
+                Some(_) => Fingerprint::of(&path)? != self.fingerprint,
+                None => path.exists(),
+            };
+            if changed {
+                anyhow::bail!(
+                    "{} was changed by another program since the editor was opened. Open the editor again to see the change.",
+                    path.display()
+                );
+            }
+            storage::write(&path, doc.to_string())?;
+        }
+        state.reload_config()?;
+        if let Some(broken) = state.broken_shows.iter().find(|broken| broken.key == key) {
+            anyhow::bail!(
+                "The show was saved, but it could not be loaded:\n{}",
+                broken.error
+            );
+        }
+        state.selected_key = key.into();
+
+        // NOTE: the `next` episode is part of the progress, not of the
+        // show config.
+        if let Some(show) = state.shows.get(key)
+            && !next.is_empty()
+            && show.next != Path::new(next)
+        {
+            let episode = show.dir.join(next);
+            state.set_next_episode(key, &episode)?;
+        }
+        Ok(())
+    }
+}
+
+/// Remove the show with the given `key`: its config file or its table
+/// in `pls.toml`. It's also dropped from `ordering` and its progress is
+/// forgotten.
+pub fn delete_show(state: &mut State, key: &str) -> anyhow::Result<()> {
+    let show = state
+        .shows
+        .get(key)
+        .ok_or_else(|| anyhow::anyhow!("Unknown show: `{key}`"))?;
+    let inline = show.config_path == state.config_path;
+    log::info!(
+        "Deleting show `{}` from {}",
+        key,
+        show.config_path.display()
+    );
+    let lock = storage::lock(&state.data_dir)?;
+    if !inline {
+        fs::remove_file(&show.config_path)?;
+    }
+
+    let mut doc = config::read_document(&state.config_path)?;
+    let mut changed = false;
+    if inline && let Some(shows) = doc.get_mut("shows").and_then(Item::as_table_like_mut) {
+        changed |= shows.remove(key).is_some();
+    }
+    if let Some(ordering) = doc.get_mut("ordering").and_then(Item::as_array_mut) {
+        let index = ordering
+            .iter()
+            .position(|entry| entry.as_str() == Some(key));
+        if let Some(index) = index {
+            ordering.remove(index);
+            changed = true;
+        }
+    }
+    if changed {
+        storage::write(&state.config_path, doc.to_string())?;
+    }
+    drop(lock);
+    let profiles = state.profiles.iter().map(|profile| Some(profile.as_str()));
+    for profile in std::iter::once(None).chain(profiles) {
+        progress::remove(&progress::profile_dir(&state.data_dir, profile), key)?;
+    }
+    state.reload_config()
+}
+
+/// A key is used as the file name of the show, so it must be a valid
+/// one and not clash with any other show or file.
+fn validate_key(state: &State, key: &str) -> anyhow::Result<()> {
+    if key.is_empty() {
+        anyhow::bail!("The show needs a key");
+    }
+    if !key
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    {
+        anyhow::bail!("The key can only contain letters, numbers, `-` and `_`");
+    }
+    let file_name = format!("{}.toml", key);
+    let is_reserved = !config::is_show_file(Path::new(&file_name));
+    let exists = state.config_dir().join(&file_name).exists()
+        || state.shows.contains_key(key)
+        || state.broken_shows.iter().any(|broken| broken.key == key);
+    if is_reserved || exists {
+        anyhow::bail!("A show with the key `{}` already exists", key);
+    }
+    Ok(())
+}
+
+/// The table defining the show: the whole document for show files,
+/// `[shows.<key>]` for shows defined in `pls.toml`.
+fn show_table<'a>(
+    doc: &'a mut Document,
+    key: &str,
+    inline: bool,
+) -> anyhow::Result<&'a mut dyn TableLike> {
+    if inline {
+        doc.get_mut("shows")
+            .and_then(|shows| shows.get_mut(key))
+            .and_then(Item::as_table_like_mut)
+            .ok_or_else(|| anyhow::anyhow!("Show `{key}` is missing from `pls.toml`"))
+    } else {
+        Ok(doc.as_table_mut())
+    }
+}
+
+/// Set the string `value` under `key` or remove the key if the value
+/// is empty.
+fn set_or_remove(table: &mut dyn TableLike, key: &str, value: &str) {
+    if value.is_empty() {
+        table.remove(key);
+    } else {
+        config::set_value(table, key, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::util::test_dir;
+
+    #[test]
+    fn save_refuses_changed_config() {
+        let dir = test_dir("editor-conflict");
+        let show_dir = dir.join("show");
+        fs::create_dir(&show_dir).unwrap();
+        fs::write(show_dir.join("Episode 1.mkv"), "").unwrap();
+        let config_dir = dir.join("config");
+        fs::create_dir(&config_dir).unwrap();
+        fs::write(
+            config_dir.join(crate::CONFIG_FILE_NAME),
+            "version = \"2.0.0\"\n",
+        )
+        .unwrap();
+        let show_path = config_dir.join("show.toml");
+        fs::write(
+            &show_path,
+            format!(
+                "name = \"Show\"\ndirectory = {:?}\n",
+                show_dir.display().to_string()
+            ),
+        )
+        .unwrap();
+        let mut state =
+            State::from_config_dir(&config_dir, &dir.join("data"), None, false).unwrap();
+
+        let mut editor = ShowEditor::edit_show(&state, "show").unwrap();
+        editor.name = "Renamed show".into();
+        let changed = format!(
+            "name = \"Edited elsewhere\"\ndirectory = {:?}\n",
+            show_dir.display().to_string()
+        );
+        fs::write(&show_path, &changed).unwrap();
+        let error = editor.save(&mut state).unwrap_err();
+        assert!(
+            error.to_string().contains("changed by another program"),
+            "{error}"
+        );
+        assert_eq!(fs::read_to_string(&show_path).unwrap(), changed);
+
+        // NOTE: a freshly opened editor sees the change and can save.
+        state.reload_config().unwrap();
+        let mut editor = ShowEditor::edit_show(&state, "show").unwrap();
+        assert_eq!(editor.name, "Edited elsewhere");
+        editor.name = "Renamed show".into();
+        editor.save(&mut state).unwrap();
+        assert_eq!(state.shows["show"].name, "Renamed show");
+        assert!(!config_dir.join(storage::LOCK_FILE_NAME).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
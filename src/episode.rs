@@ -0,0 +1,207 @@
+use std::{fmt, path::Path};
+
+/// Season and episode number parsed from an episode's file name.
+///
+/// Shows with absolute numbering (e.g. `Episode 112`) have no season
+/// unless one can be found in the name of the parent directory
+/// (e.g. `Season 2/Episode 12.mkv`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EpisodeNumber {
+    pub season: Option<u32>,
+    pub episode: u32,
+}
+
+impl EpisodeNumber {
+    /// Parse the season and episode number from the episode's path
+    /// relative to the show directory.
+    ///
+    /// Recognises (case insensitive) `S01E02`, `1x02`, `Episode 12`,
+    /// `Ep 12`, `E12` and plain absolute numbers (`Show - 112`).
+    pub fn parse(relative_path: &Path) -> Option<Self> {
+        let stem = relative_path.file_stem()?.to_string_lossy().to_lowercase();
+        let chars = stem.chars().collect::<Vec<_>>();
+
+        if let Some((season, episode)) = parse_season_episode(&chars).or_else(|| parse_nx(&chars)) {
+            return Some(EpisodeNumber {
+                season: Some(season),
+                episode,
+            });
+        }
+
+        let episode = parse_episode_word(&chars).or_else(|| parse_absolute(&chars))?;
+        let season = relative_path
+            .parent()
+            .and_then(Path::file_name)
+            .and_then(|dir| parse_season_dir(&dir.to_string_lossy().to_lowercase()));
+        Some(EpisodeNumber { season, episode })
+    }
+}
+
+impl fmt::Display for EpisodeNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self.season {
+            Some(season) => write!(f, "S{:02}E{:02}", season, self.episode),
+            None => write!(f, "E{:02}", self.episode),
+        }
+    }
+}
+
+/// Read the digits starting at `start`. Returns the number and the
+/// index right after the last digit.
+fn number_at(chars: &[char], start: usize) -> Option<(u32, usize)> {
+    let end = chars[start..]
+        .iter()
+        .position(|c| !c.is_ascii_digit())
+        .map_or(chars.len(), |len| start + len);
+    if end == start || end - start > 4 {
+        return None;
+    }
+    let number = chars[start..end].iter().collect::<String>().parse().ok()?;
+    Some((number, end))
+}
+
+/// `true` if there's no letter or digit right before `index`.
+fn word_start(chars: &[char], index: usize) -> bool {
+    index == 0 || !chars[index - 1].is_alphanumeric()
+}
+
+/// `true` if there's no letter or digit at `index`.
+fn word_end(chars: &[char], index: usize) -> bool {
+    index == chars.len() || !chars[index].is_alphanumeric()
+}
+
+/// `s01e02`, `s1e2`, `s01.e02`, `s01 e02`
+fn parse_season_episode(chars: &[char]) -> Option<(u32, u32)> {
+    (0..chars.len()).find_map(|index| {
+        if chars[index] != 's' || !word_start(chars, index) {
+            return None;
+        }
+        let (season, mut next) = number_at(chars, index + 1)?;
+        while next < chars.len() && matches!(chars[next], '.' | ' ' | '-' | '_') {
+            next += 1;
+        }
+        if chars.get(next) != Some(&'e') {
+            return None;
+        }
+        let (episode, _) = number_at(chars, next + 1)?;
+        Some((season, episode))
+    })
+}
+
+/// `1x02`, `01x02`
+fn parse_nx(chars: &[char]) -> Option<(u32, u32)> {
+    (0..chars.len()).find_map(|index| {
+        if !word_start(chars, index) {
+            return None;
+        }
+        let (season, x) = number_at(chars, index)?;
+        if x - index > 2 || chars.get(x) != Some(&'x') {
+            return None;
+        }
+        let (episode, end) = number_at(chars, x + 1)?;
+        (end - x - 1 <= 3 && word_end(chars, end)).then_some((season, episode))
+    })
+}
+
+/// `episode 12`, `episode.12`, `ep 12`, `ep12`, `e12`
+fn parse_episode_word(chars: &[char]) -> Option<u32> {
+    (0..chars.len()).find_map(|index| {
+        if !word_start(chars, index) {
+            return None;
+        }
+        let rest = &chars[index..];
+        let word_len = ["episode", "ep", "e"].iter().find_map(|word| {
+            let word = word.chars().collect::<Vec<_>>();
+            rest.starts_with(&word).then_some(word.len())
+        })?;
+        let mut next = index + word_len;
+        while next < chars.len() && matches!(chars[next], '.' | ' ' | '-' | '_' | '#') {
+            next += 1;
+        }
+        let (episode, end) = number_at(chars, next)?;
+        word_end(chars, end).then_some(episode)
+    })
+}
+
+/// The last standalone number in the name that doesn't look like a
+/// year. E.g. `Show - 112`.
+fn parse_absolute(chars: &[char]) -> Option<u32> {
+    let mut result = None;
+    let mut index = 0;
+    while index < chars.len() {
+        if word_start(chars, index)
+            && let Some((number, end)) = number_at(chars, index)
+            && word_end(chars, end)
+        {
+            let is_year = end - index == 4 && (1900..2100).contains(&number);
+            if !is_year {
+                result = Some(number);
+            }
+            index = end;
+        } else {
+            index += 1;
+        }
+    }
+    result
+}
+
+/// `season 2`, `season.02`, `series 2`, `s02`, `s2`
+fn parse_season_dir(name: &str) -> Option<u32> {
+    let chars = name.chars().collect::<Vec<_>>();
+    (0..chars.len()).find_map(|index| {
+        if !word_start(&chars, index) {
+            return None;
+        }
+        let rest = &chars[index..];
+        let word_len = ["season", "series", "s"].iter().find_map(|word| {
+            let word = word.chars().collect::<Vec<_>>();
+            rest.starts_with(&word).then_some(word.len())
+        })?;
+        let mut next = index + word_len;
+        while next < chars.len() && matches!(chars[next], '.' | ' ' | '-' | '_') {
+            next += 1;
+        }
+        let (season, end) = number_at(&chars, next)?;
+        word_end(&chars, end).then_some(season)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let cases = [
+            ("Show S01E10.mkv", Some((Some(1), 10))),
+            ("show.s1.e2.mkv", Some((Some(1), 2))),
+            ("Show 1x02 Title.mkv", Some((Some(1), 2))),
+            ("Episode 12.mkv", Some((None, 12))),
+            ("Show Ep.7.mkv", Some((None, 7))),
+            ("Season 2/E05.mkv", Some((Some(2), 5))),
+            ("Series 3/Episode 1.mkv", Some((Some(3), 1))),
+            ("Show - 112.mkv", Some((None, 112))),
+            ("Show 2019 - 03.mkv", Some((None, 3))),
+            ("Show 2019.mkv", None),
+            ("Special.mkv", None),
+        ];
+        for (path, expected) in cases {
+            let expected = expected.map(|(season, episode)| EpisodeNumber { season, episode });
+            assert_eq!(EpisodeNumber::parse(Path::new(path)), expected, "{path}");
+        }
+    }
+
+    #[test]
+    fn display() {
+        let number = EpisodeNumber {
+            season: Some(1),
+            episode: 2,
+        };
+        assert_eq!(number.to_string(), "S01E02");
+        let number = EpisodeNumber {
+            season: None,
+            episode: 112,
+        };
+        assert_eq!(number.to_string(), "E112");
+    }
+}
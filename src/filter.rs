@@ -0,0 +1,146 @@
+use std::path::{Component, Path};
+
+use glob::{MatchOptions, Pattern};
+
+/// Video file extensions treated as episodes by default.
+pub const VIDEO_EXTENSIONS: &[&str] = &[
+    "3gp", "avi", "divx", "flv", "m2ts", "m4v", "mkv", "mov", "mp4", "mpeg", "mpg", "ogm", "ogv",
+    "rm", "rmvb", "ts", "vob", "webm", "wmv",
+];
+
+/// Audio file extensions treated as episodes by default.
+pub const AUDIO_EXTENSIONS: &[&str] = &[
+    "aac", "ac3", "flac", "m4a", "m4b", "mka", "mp3", "oga", "ogg", "opus", "wav", "wma",
+];
+
+/// Decides which files in the show's directory are episodes.
+///
+/// A file is an episode if its extension is one of `extensions`
+/// (case insensitive, `*` matches any file) and neither its path
+/// relative to the show directory nor any of its components matches
+/// any of the `ignore` glob patterns.
+#[derive(Clone, Debug)]
+pub struct EpisodeFilter {
+    pub extensions: Vec<String>,
+    pub ignore: Vec<Pattern>,
+}
+
+impl Default for EpisodeFilter {
+    fn default() -> Self {
+        EpisodeFilter {
+            extensions: VIDEO_EXTENSIONS
+                .iter()
+                .chain(AUDIO_EXTENSIONS)
+                .map(|&e| e.into())
+                .collect(),
+            ignore: vec![],
+        }
+    }
+}
+
+impl EpisodeFilter {
+    /// Create the filter from the show's `extensions` and `ignore`
+    /// values. Falls back to the video and audio extensions if
+    /// `extensions` is `None`.
+    pub fn new(extensions: Option<Vec<String>>, ignore: &[String]) -> anyhow::Result<Self> {
+        let extensions = match extensions {
+            Some(extensions) => extensions
+                .iter()
+                .map(|e| e.trim_start_matches('.').to_lowercase())
+                .collect(),
+            None => Self::default().extensions,
+        };
+        let ignore = ignore
+            .iter()
+            .map(|pattern| {
+                Pattern::new(pattern).map_err(|error| {
+                    anyhow::anyhow!("Invalid `ignore` pattern `{pattern}`: {error}")
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(EpisodeFilter { extensions, ignore })
+    }
+
+    /// Return `true` if `path` (a file inside `dir`) is an episode.
+    pub fn matches(&self, dir: &Path, path: &Path) -> bool {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let known_extension = self.extensions.iter().any(|e| e == "*" || *e == extension);
+        if !known_extension {
+            return false;
+        }
+
+        let relative = path.strip_prefix(dir).unwrap_or(path);
+        let options = MatchOptions {
+            case_sensitive: false,
+            ..MatchOptions::default()
+        };
+        let ignored = self.ignore.iter().any(|pattern| {
+            pattern.matches_path_with(relative, options)
+                || relative.components().any(|component| match component {
+                    Component::Normal(name) => {
+                        pattern.matches_with(&name.to_string_lossy(), options)
+                    }
+                    _ => false,
+                })
+        });
+        !ignored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(extensions: Option<&[&str]>, ignore: &[&str]) -> EpisodeFilter {
+        let extensions = extensions.map(|e| e.iter().map(|&e| e.into()).collect());
+        let ignore = ignore.iter().map(|&p| p.into()).collect::<Vec<String>>();
+        EpisodeFilter::new(extensions, &ignore).unwrap()
+    }
+
+    #[test]
+    fn extensions() {
+        let dir = Path::new("/show");
+        let default = filter(None, &[]);
+        assert!(default.matches(dir, Path::new("/show/Episode 1.mkv")));
+        assert!(default.matches(dir, Path::new("/show/Episode 1.MKV")));
+        assert!(default.matches(dir, Path::new("/show/Chapter 1.mp3")));
+        assert!(!default.matches(dir, Path::new("/show/Episode 1.srt")));
+        assert!(!default.matches(dir, Path::new("/show/README")));
+
+        let custom = filter(Some(&[".TXT", "md"]), &[]);
+        assert!(custom.matches(dir, Path::new("/show/S1E1.txt")));
+        assert!(custom.matches(dir, Path::new("/show/S1E1.Md")));
+        assert!(!custom.matches(dir, Path::new("/show/S1E1.mkv")));
+
+        let any = filter(Some(&["*"]), &[]);
+        assert!(any.matches(dir, Path::new("/show/S1E1.whatever")));
+        assert!(any.matches(dir, Path::new("/show/S1E1")));
+    }
+
+    #[test]
+    fn ignore() {
+        let dir = Path::new("/show");
+        let filter = filter(None, &["Extras", "*sample*", "Season 1/*.mp4"]);
+        assert!(filter.matches(dir, Path::new("/show/Season 1/Episode 1.mkv")));
+        assert!(!filter.matches(dir, Path::new("/show/Extras/Making of.mkv")));
+        assert!(!filter.matches(dir, Path::new("/show/Season 1/extras/Bloopers.mkv")));
+        assert!(!filter.matches(dir, Path::new("/show/Episode 1 SAMPLE.mkv")));
+        assert!(!filter.matches(dir, Path::new("/show/Season 1/Episode 2.mp4")));
+        assert!(filter.matches(dir, Path::new("/show/Season 2/Episode 2.mp4")));
+        // NOTE: the show directory itself is never matched.
+        let dir = Path::new("/media/Extras/show");
+        assert!(filter.matches(dir, Path::new("/media/Extras/show/Episode 1.mkv")));
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        let error = EpisodeFilter::new(None, &["[".into()]).unwrap_err();
+        assert!(
+            error.to_string().contains("Invalid `ignore` pattern `[`"),
+            "{error}"
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use crate::storage;
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use toml_edit::{ArrayOfTables, Datetime, Document, Table};
+
+/// Name of the watch history file inside the data directory (or the
+/// directory of a profile).
+pub const HISTORY_FILE_NAME: &str = "history.toml";
+
+/// A single play of an episode.
+#[derive(Clone, Debug)]
+pub struct Play {
+    /// Key of the show the episode belongs to.
+    pub show: String,
+    /// Full path to the episode.
+    pub episode: PathBuf,
+    pub timestamp: SystemTime,
+    /// Name of the computer the episode was played on.
+    pub hostname: String,
+}
+
+impl Play {
+    /// A play of the `episode` from the `show` happening right now on
+    /// this computer.
+    pub fn now(show: &str, episode: &Path) -> Self {
+        Play {
+            show: show.into(),
+            episode: episode.into(),
+            timestamp: SystemTime::now(),
+            hostname: crate::util::hostname().unwrap_or_default(),
+        }
+    }
+
+    /// The timestamp in the RFC 3339 format (in UTC, e.g.
+    /// `2022-02-05T19:53:34Z`).
+    pub fn timestamp_str(&self) -> String {
+        humantime::format_rfc3339_seconds(self.timestamp).to_string()
+    }
+
+    fn to_table(&self) -> anyhow::Result<Table> {
+        let mut table = Table::new();
+        table["show"] = toml_edit::value(self.show.as_str());
+        table["episode"] = toml_edit::value(self.episode.display().to_string());
+        table["timestamp"] = toml_edit::value(self.timestamp_str().parse::<Datetime>()?);
+        table["hostname"] = toml_edit::value(self.hostname.as_str());
+        Ok(table)
+    }
+
+    fn from_table(table: &Table) -> Option<Self> {
+        let timestamp = table.get("timestamp")?.as_datetime()?.to_string();
+        Some(Play {
+            show: table.get("show")?.as_str()?.into(),
+            episode: table.get("episode")?.as_str()?.into(),
+            timestamp: humantime::parse_rfc3339_weak(&timestamp).ok()?,
+            hostname: table
+                .get("hostname")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .into(),
+        })
+    }
+}
+
+/// Append the play to the history file in `dir` (the data directory
+/// or the directory of a profile).
+///
+/// The file is only ever appended to, each play is a `[[play]]`
+/// table.
+pub fn record(dir: &Path, play: &Play) -> anyhow::Result<()> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(HISTORY_FILE_NAME);
+    log::info!("Recording play in {}: {:?}", path.display(), play);
+    let mut plays = ArrayOfTables::new();
+    plays.push(play.to_table()?);
+    let mut doc = Document::new();
+    doc["play"] = toml_edit::Item::ArrayOfTables(plays);
+
+    // NOTE: appending doesn't need a temporary file, an interrupted
+    // write only loses the play being recorded.
+    let _lock = storage::lock(dir)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    file.write_all(doc.to_string().as_bytes())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Read all the plays from the history file in `dir`, oldest first.
+/// Returns an empty list if there's no history yet.
+pub fn read(dir: &Path) -> anyhow::Result<Vec<Play>> {
+    let path = dir.join(HISTORY_FILE_NAME);
+    let toml = match fs::read_to_string(&path) {
+        Ok(toml) => toml,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(error) => return Err(error.into()),
+    };
+    let doc = toml.parse::<Document>()?;
+    let plays = doc
+        .get("play")
+        .and_then(toml_edit::Item::as_array_of_tables)
+        .map(|plays| plays.iter().filter_map(Play::from_table).collect())
+        .unwrap_or_default();
+    Ok(plays)
+}
+
+/// Return the last `count` plays of the show with the given `key`,
+/// newest first.
+pub fn recent<'a>(plays: &'a [Play], key: &str, count: usize) -> Vec<&'a Play> {
+    plays
+        .iter()
+        .rev()
+        .filter(|play| play.show == key)
+        .take(count)
+        .collect()
+}
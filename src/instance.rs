@@ -0,0 +1,355 @@
+//! Keeping a single pls window per profile: the window listens on a
+//! local socket in the profile's data directory and later launches of
+//! pls hand their command over to it instead of loading their own
+//! (soon outdated) copy of the progress.
+//!
+//! The messages are single lines of JSON. A request is
+//! `{"args": ["play", "<key>"]}` (no arguments just check that the
+//! window is there), the reply is `{"ok": true, "message": "..."}`.
+
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+    },
+    thread,
+    time::Duration,
+};
+
+use transport::{Listener, Stream};
+
+/// Name of the socket inside the data directory (or the directory of
+/// a profile). Without Unix sockets (i.e. on Windows), it's a file
+/// with the port the window listens on.
+pub const SOCKET_FILE_NAME: &str = "pls.sock";
+
+/// How long to wait for the running window to handle a command. It
+/// only does so when it's painted next, which happens right away
+/// unless it's stuck.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the window waits for a request after a connection.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The answer of the running window to a forwarded command.
+#[derive(Clone, Debug)]
+pub struct Reply {
+    /// Whether the command succeeded.
+    pub ok: bool,
+    /// The output of the command or the error.
+    pub message: String,
+}
+
+/// Hand the command `args` over to the pls window running with the
+/// socket in `dir`. Returns `None` if there's no window running,
+/// removing the socket if it was left behind by one that didn't quit
+/// cleanly.
+pub fn forward(dir: &Path, args: &[String]) -> anyhow::Result<Option<Reply>> {
+    let path = dir.join(SOCKET_FILE_NAME);
+    let stale = || -> anyhow::Result<Option<Reply>> {
+        log::info!("Removing a stale socket: {}", path.display());
+        remove_socket(&path)?;
+        Ok(None)
+    };
+    let mut stream = match transport::connect(&path) {
+        Ok(stream) => stream,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error)
+            if matches!(
+                error.kind(),
+                io::ErrorKind::ConnectionRefused | io::ErrorKind::InvalidData
+            ) =>
+        {
+            return stale();
+        }
+        Err(error) => return Err(error.into()),
+    };
+    log::info!("Forwarding {:?} to the running pls window", args);
+    stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+    // NOTE: without Unix sockets, the port of a window that didn't
+    // quit cleanly can be taken by another program by now. Whatever
+    // doesn't answer like pls isn't pls.
+    if writeln!(stream, "{}", serde_json::json!({ "args": args })).is_err() {
+        return stale();
+    }
+    let mut line = String::new();
+    match BufReader::new(stream).read_line(&mut line) {
+        Ok(0) => return stale(),
+        Ok(_) => {}
+        Err(error)
+            if matches!(
+                error.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) =>
+        {
+            if cfg!(unix) {
+                anyhow::bail!("pls is already running but didn't answer")
+            }
+            return stale();
+        }
+        Err(_) => return stale(),
+    }
+    let reply: serde_json::Value = serde_json::from_str(&line).unwrap_or_default();
+    let Some(ok) = reply["ok"].as_bool() else {
+        return stale();
+    };
+    Ok(Some(Reply {
+        ok,
+        message: reply["message"].as_str().unwrap_or_default().into(),
+    }))
+}
+
+/// A command forwarded by another launch of pls, waiting for the
+/// window to run it.
+#[derive(Debug)]
+pub struct Request {
+    pub args: Vec<String>,
+    stream: Stream,
+}
+
+impl Request {
+    /// Send the `result` of the command back.
+    pub fn reply(mut self, result: anyhow::Result<String>) {
+        let reply = match result {
+            Ok(message) => serde_json::json!({ "ok": true, "message": message }),
+            Err(error) => serde_json::json!({ "ok": false, "message": error.to_string() }),
+        };
+        // NOTE: the other side may have given up waiting already.
+        if let Err(error) = writeln!(self.stream, "{}", reply) {
+            log::warn!("Could not reply to a forwarded command: {}", error);
+        }
+    }
+}
+
+/// The socket the pls window listens on for commands.
+#[derive(Debug)]
+pub struct Server {
+    dir: PathBuf,
+    listener: Option<Listener>,
+    requests: Option<Receiver<Request>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Server {
+    /// Create the socket in `dir`. Fails if another pls window is
+    /// already listening there.
+    pub fn bind(dir: &Path) -> anyhow::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(SOCKET_FILE_NAME);
+        if transport::connect(&path).is_ok() {
+            anyhow::bail!("pls is already running with {}", path.display());
+        }
+        remove_socket(&path)?;
+        let listener = transport::bind(&path)?;
+        log::info!("Listening for commands on {}", path.display());
+        Ok(Server {
+            dir: dir.into(),
+            listener: Some(listener),
+            requests: None,
+            stopped: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Start accepting the commands in the background. `on_request` is
+    /// called for every one, to wake the window up to run it.
+    pub fn serve(&mut self, on_request: impl Fn() + Send + 'static) {
+        let Some(listener) = self.listener.take() else {
+            return;
+        };
+        let (sender, receiver) = mpsc::channel();
+        self.requests = Some(receiver);
+        let stopped = self.stopped.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                match stream.and_then(read_request) {
+                    Ok(request) => {
+                        if sender.send(request).is_err() {
+                            break;
+                        }
+                        on_request();
+                    }
+                    Err(error) => log::warn!("Invalid forwarded command: {}", error),
+                }
+            }
+        });
+    }
+
+    /// The commands received since the last call.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests
+            .as_ref()
+            .map(|requests| requests.try_iter().collect())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let path = self.dir.join(SOCKET_FILE_NAME);
+        // NOTE: wake the background thread up so it notices it should
+        // stop.
+        self.stopped.store(true, Ordering::SeqCst);
+        if self.requests.is_some() {
+            let _ = transport::connect(&path);
+        }
+        let _ = fs::remove_file(&path);
+    }
+}
+
+fn read_request(stream: Stream) -> io::Result<Request> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(stream.try_clone()?).read_line(&mut line)?;
+    let request: serde_json::Value = serde_json::from_str(&line)?;
+    let args = request["args"]
+        .as_array()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing `args`"))?
+        .iter()
+        .filter_map(|arg| arg.as_str().map(String::from))
+        .collect();
+    Ok(Request { args, stream })
+}
+
+/// Remove the socket of a pls window that's no longer running.
+fn remove_socket(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+mod transport {
+    use std::{
+        io,
+        os::unix::net::{UnixListener, UnixStream},
+        path::Path,
+    };
+
+    pub type Listener = UnixListener;
+    pub type Stream = UnixStream;
+
+    pub fn bind(path: &Path) -> io::Result<Listener> {
+        UnixListener::bind(path)
+    }
+
+    pub fn connect(path: &Path) -> io::Result<Stream> {
+        UnixStream::connect(path)
+    }
+}
+
+/// A TCP socket on the loopback interface with its port written into
+/// the socket file.
+#[cfg(not(unix))]
+mod transport {
+    use std::{
+        fs, io,
+        net::{Ipv4Addr, TcpListener, TcpStream},
+        path::Path,
+    };
+
+    pub type Listener = TcpListener;
+    pub type Stream = TcpStream;
+
+    pub fn bind(path: &Path) -> io::Result<Listener> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        crate::storage::write(path, listener.local_addr()?.port().to_string())?;
+        Ok(listener)
+    }
+
+    pub fn connect(path: &Path) -> io::Result<Stream> {
+        let port = fs::read_to_string(path)?
+            .trim()
+            .parse::<u16>()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::util::test_dir;
+
+    #[test]
+    fn forward_to_window() {
+        let dir = test_dir("instance-forward");
+        assert!(forward(&dir, &[]).unwrap().is_none());
+
+        let mut server = Server::bind(&dir).unwrap();
+        assert!(Server::bind(&dir).is_err());
+        server.serve(|| {});
+        let window = thread::spawn(move || {
+            let mut handled = 0;
+            while handled < 2 {
+                for request in server.requests() {
+                    let result = match request.args.as_slice() {
+                        [] => Ok("pls is already open".to_string()),
+                        args => Err(anyhow::anyhow!("Unknown show: `{}`", args[1])),
+                    };
+                    request.reply(result);
+                    handled += 1;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+
+        let reply = forward(&dir, &[]).unwrap().unwrap();
+        assert!(reply.ok);
+        assert_eq!(reply.message, "pls is already open");
+        let reply = forward(&dir, &["play".into(), "show".into()])
+            .unwrap()
+            .unwrap();
+        assert!(!reply.ok);
+        assert_eq!(reply.message, "Unknown show: `show`");
+
+        // NOTE: the server is dropped once the window is done.
+        window.join().unwrap();
+        assert!(!dir.join(SOCKET_FILE_NAME).exists());
+        assert!(forward(&dir, &[]).unwrap().is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// The socket of a pls window that didn't quit cleanly.
+    #[test]
+    fn stale_socket() {
+        let dir = test_dir("instance-stale");
+        let path = dir.join(SOCKET_FILE_NAME);
+        drop(transport::bind(&path).unwrap());
+        assert!(path.exists());
+        assert!(forward(&dir, &[]).unwrap().is_none());
+        assert!(!path.exists());
+
+        drop(transport::bind(&path).unwrap());
+        let server = Server::bind(&dir).unwrap();
+        assert!(path.exists());
+        drop(server);
+        assert!(!path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Something else answers on the socket, e.g. another program got
+    /// the port of a pls window that didn't quit cleanly.
+    #[test]
+    fn other_program() {
+        let dir = test_dir("instance-other");
+        let path = dir.join(SOCKET_FILE_NAME);
+        let listener = transport::bind(&path).unwrap();
+        let other = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            writeln!(stream, "HTTP/1.1 400 Bad Request").unwrap();
+        });
+        assert!(forward(&dir, &[]).unwrap().is_none());
+        assert!(!path.exists());
+        other.join().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,85 @@
+//! The core of pls: loading the config directory, enumerating the
+//! shows and keeping track of the next episode to play.
+//!
+//! The config directory contains the main `pls.toml` file and the
+//! shows, either as `<key>.toml` files or as `[shows.<key>]` tables in
+//! `pls.toml` (see [`config`]). Load it with
+//! [`State::from_config_dir`](state::State::from_config_dir), look up
+//! a [`Show`](show::Show) in [`State::shows`](state::State::shows),
+//! call [`Show::advance_to_next_episode`](show::Show::advance_to_next_episode)
+//! after playing it and persist the progress with
+//! [`State::save_progress`](state::State::save_progress).
+//!
+//! The progress of the shows, the watch history and the known episodes
+//! are kept in the data directory (see [`data_dir`] and [`progress`])
+//! so the config directory is only ever read, apart from the show
+//! editor and migrating an older config (see [`config::migrate`]).
+
+pub mod check;
+pub mod config;
+pub mod editor;
+pub mod episode;
+pub mod filter;
+pub mod history;
+pub mod instance;
+#[cfg(unix)]
+pub mod mpv;
+pub mod player;
+pub mod playlist;
+pub mod progress;
+pub mod show;
+pub mod snapshot;
+pub mod state;
+pub mod storage;
+pub mod util;
+pub mod watch;
+
+use std::path::PathBuf;
+
+/// The application name. Used for the config directory, the
+/// executable and the release archive.
+pub const APPLICATION: &str = "pls";
+
+/// Name of the main config file inside the config directory.
+pub const CONFIG_FILE_NAME: &str = "pls.toml";
+
+/// Return the directory holding `pls.toml` and the show files.
+///
+/// This is the platform's config directory for pls, unless the `test`
+/// feature is enabled (or the platform directory can't be
+/// determined). Then it's the `test/pls` directory in this
+/// repository.
+pub fn config_dir() -> PathBuf {
+    let test_config_dir = PathBuf::from("test/pls");
+    if cfg!(feature = "test") {
+        test_config_dir
+    } else {
+        project_dirs()
+            .map(|d| d.config_dir().to_owned())
+            .unwrap_or(test_config_dir)
+    }
+}
+
+/// Return the directory holding the data pls keeps track of: the
+/// progress of the shows, the watch history and the known episodes.
+///
+/// This is the platform's data directory for pls, unless the `test`
+/// feature is enabled (or the platform directory can't be
+/// determined). Then it's the `test/pls-data` directory in this
+/// repository.
+pub fn data_dir() -> PathBuf {
+    let test_data_dir = PathBuf::from("test/pls-data");
+    if cfg!(feature = "test") {
+        test_data_dir
+    } else {
+        project_dirs()
+            .map(|d| d.data_dir().to_owned())
+            .unwrap_or(test_data_dir)
+    }
+}
+
+fn project_dirs() -> Option<directories::ProjectDirs> {
+    let qualifier = ""; // NOTE: something like com.mydomain
+    let organisation = ""; // NOTE: Try Jumping
+    directories::ProjectDirs::from(qualifier, organisation, APPLICATION)
+}
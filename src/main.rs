@@ -1,18 +1,79 @@
 #![windows_subsystem = "windows"]
 
-mod show;
-mod state;
-mod util;
+mod cli;
 mod window;
 
+use pls::{instance, state};
+
+use std::path::{Path, PathBuf};
+
 use simplelog::{CombinedLogger, Config, LevelFilter, SharedLogger, SimpleLogger, WriteLogger};
 
+/// Name of the log file inside the data directory.
+const LOG_FILE_NAME: &str = "pls.log";
+
 struct Pls {
     state: state::State,
+    /// Listens for the commands of later launches of pls.
+    server: Option<instance::Server>,
+    /// The profile directory the `server` is for, i.e. the one of the
+    /// profile the window is showing.
+    instance_dir: PathBuf,
+}
+
+impl Pls {
+    /// Run the commands forwarded by later launches of pls. After
+    /// switching to another profile, the socket moves to its directory
+    /// so the commands for the previous profile no longer end up here.
+    fn run_forwarded_commands(&mut self, ctx: &egui::CtxRef) {
+        let profile_dir = self.state.profile_dir();
+        if profile_dir != self.instance_dir {
+            if let Some(server) = self.server.take() {
+                for request in server.requests() {
+                    request.reply(Err(anyhow::anyhow!(
+                        "pls switched to another profile. Try again."
+                    )));
+                }
+            }
+            self.server = bind_server(&profile_dir);
+            self.instance_dir = profile_dir;
+            if let Some(server) = &mut self.server {
+                let repaint_ctx = ctx.clone();
+                server.serve(move || repaint_ctx.request_repaint());
+            }
+        }
+
+        let Some(server) = &self.server else {
+            return;
+        };
+        for request in server.requests() {
+            let repaint_ctx = ctx.clone();
+            let result = cli::run_forwarded(&mut self.state, &request.args, move || {
+                repaint_ctx.request_repaint()
+            });
+            if let Err(error) = &result {
+                self.state.error = Some(error.to_string());
+            }
+            request.reply(result);
+        }
+    }
+}
+
+/// Listen for the commands of later launches of pls in `dir`. `None`
+/// if another pls window is already listening there.
+fn bind_server(dir: &Path) -> Option<instance::Server> {
+    match instance::Server::bind(dir) {
+        Ok(server) => Some(server),
+        Err(error) => {
+            log::error!("Could not listen for commands: {}", error);
+            None
+        }
+    }
 }
 
 impl epi::App for Pls {
     fn update(&mut self, ctx: &egui::CtxRef, _frame: &epi::Frame) {
+        self.run_forwarded_commands(ctx);
         egui::CentralPanel::default().show(ctx, |ui| {
             window::show(&mut self.state, ui);
         });
@@ -50,20 +111,72 @@ impl epi::App for Pls {
         theme.widgets.inactive.bg_stroke.color = egui::Color32::from_gray(192);
         theme.widgets.inactive.bg_stroke.width = 2.0;
         ctx.set_visuals(theme);
+
+        let repaint_ctx = ctx.clone();
+        if let Err(error) = self
+            .state
+            .start_watching(move || repaint_ctx.request_repaint())
+        {
+            log::error!("Could not watch the show directories: {}", error);
+        }
+
+        if let Some(server) = &mut self.server {
+            let repaint_ctx = ctx.clone();
+            server.serve(move || repaint_ctx.request_repaint());
+        }
     }
 
     fn name(&self) -> &str {
-        "pls"
+        pls::APPLICATION
     }
 }
 
 fn main() -> anyhow::Result<()> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    // NOTE: `--profile NAME` can go before the command (or be the only
+    // argument to open the window with that profile).
+    let profile = match args.first().map(String::as_str) {
+        Some("--profile") if args.len() >= 2 => {
+            let profile = args.remove(1);
+            args.remove(0);
+            Some(profile)
+        }
+        Some("--profile") => anyhow::bail!("Missing the profile name after `--profile`"),
+        _ => None,
+    };
+    let cli_mode = !args.is_empty();
+    if cli::is_help(&args) {
+        print!("{}", cli::USAGE);
+        return Ok(());
+    }
+    if cli_mode && !cli::is_valid(&args) {
+        eprint!("{}", cli::USAGE);
+        anyhow::bail!("Unknown command or wrong arguments: {}", args.join(" "));
+    }
+
     // Set up logging
     let log_level = LevelFilter::Trace;
-    let mut loggers =
-        vec![SimpleLogger::new(log_level, Config::default()) as Box<dyn SharedLogger>];
+    let mut loggers: Vec<Box<dyn SharedLogger>> = vec![];
+
+    // NOTE: in the command line mode, the standard output is for the
+    // command's results only. The logs still go to `pls.log`.
+    if !cli_mode {
+        loggers.push(SimpleLogger::new(log_level, Config::default()));
+    }
 
-    if let Ok(logfile) = std::fs::File::create("pls.log") {
+    // NOTE: the log is in the data directory so running a command
+    // doesn't leave it in the current directory. Commands append to it
+    // rather than wiping the log of a running window.
+    let data_dir = pls::data_dir();
+    let logfile = std::fs::create_dir_all(&data_dir).and_then(|()| {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(cli_mode)
+            .truncate(!cli_mode)
+            .open(data_dir.join(LOG_FILE_NAME))
+    });
+    if let Ok(logfile) = logfile {
         loggers.push(WriteLogger::new(log_level, Config::default(), logfile));
     }
 
@@ -73,29 +186,46 @@ fn main() -> anyhow::Result<()> {
 
     log_panics::init();
 
-    let qualifier = ""; // NOTE: something like com.mydomain
-    let organisation = ""; // NOTE: Try Jumping
-    let application = "pls";
-
     log::debug!("Hostname: {:?}", hostname::get());
 
-    let test_config_dir = std::path::PathBuf::from("test/pls");
-    let config_dir = if cfg!(feature = "test") {
-        test_config_dir
-    } else {
-        directories::ProjectDirs::from(qualifier, organisation, application)
-            .map(|d| d.config_dir().to_owned())
-            .unwrap_or(test_config_dir)
-    };
-    let config_path = config_dir.join("pls.toml");
+    let config_dir = pls::config_dir();
 
     log::debug!("Current directory: {:?}", std::env::current_dir());
-    log::info!("Config location: {:?}", config_path);
-    let full_config_path = config_path.canonicalize()?;
-    let state = state::State::new(&full_config_path)?;
+    log::info!("Config location: {:?}", config_dir);
+    log::info!("Data location: {:?}", data_dir);
+    log::info!("Profile: {:?}", profile);
+
+    // NOTE: a window already running with the same profile takes over
+    // the commands that change the progress (and a launch without one).
+    let instance_dir = pls::progress::profile_dir(&data_dir, profile.as_deref());
+    if args
+        .first()
+        .is_none_or(|command| cli::FORWARDED_COMMANDS.contains(&command.as_str()))
+        && let Some(reply) = instance::forward(&instance_dir, &args)?
+    {
+        if !reply.ok {
+            anyhow::bail!(reply.message);
+        }
+        println!("{}", reply.message);
+        return Ok(());
+    }
+
+    let read_only = args
+        .first()
+        .is_some_and(|command| cli::READ_ONLY_COMMANDS.contains(&command.as_str()));
+    let mut state =
+        state::State::from_config_dir(&config_dir, &data_dir, profile.as_deref(), read_only)?;
     log::info!("Config version: {}", state.config_version);
 
-    let app = Pls { state };
+    if cli_mode {
+        return cli::run(&mut state, &args);
+    }
+
+    let app = Pls {
+        state,
+        server: bind_server(&instance_dir),
+        instance_dir,
+    };
     let native_options = egui_glow::NativeOptions {
         initial_window_size: Some(egui::Vec2::new(600.0, 800.0)),
         ..egui_glow::NativeOptions::default()
@@ -0,0 +1,292 @@
+//! Integration with mpv's JSON IPC (see the `--input-ipc-server`
+//! option): launch the episode, keep track of how far it got and
+//! resume from there next time.
+
+use crate::player::{Outcome, Playback, Player};
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    process::Child,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often the playback position is checked.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long to wait for mpv to create the IPC socket.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The player used when `mpv_ipc` is enabled but there's no `player`.
+pub fn default_player() -> Player {
+    Player {
+        program: "mpv".into(),
+        args: vec![],
+    }
+}
+
+/// A connection to mpv's JSON IPC socket.
+pub struct Client {
+    writer: UnixStream,
+    reader: BufReader<UnixStream>,
+    request_id: u64,
+}
+
+impl Client {
+    pub fn connect(socket: &Path) -> io::Result<Self> {
+        let writer = UnixStream::connect(socket)?;
+        writer.set_read_timeout(Some(POLL_INTERVAL))?;
+        let reader = BufReader::new(writer.try_clone()?);
+        Ok(Client {
+            writer,
+            reader,
+            request_id: 0,
+        })
+    }
+
+    /// Keep trying to connect until the socket exists or `timeout`
+    /// passes. mpv only creates the socket once it starts up.
+    pub fn connect_with_timeout(socket: &Path, timeout: Duration) -> io::Result<Self> {
+        let started = Instant::now();
+        loop {
+            match Self::connect(socket) {
+                Ok(client) => return Ok(client),
+                Err(error) if started.elapsed() >= timeout => return Err(error),
+                Err(_) => thread::sleep(Duration::from_millis(100)),
+            }
+        }
+    }
+
+    /// Return the numeric property `name` (e.g. `time-pos` or
+    /// `duration`). `None` if the property isn't available (e.g. the
+    /// file hasn't loaded yet).
+    pub fn get_property(&mut self, name: &str) -> io::Result<Option<f64>> {
+        self.request_id += 1;
+        let request = serde_json::json!({
+            "command": ["get_property", name],
+            "request_id": self.request_id,
+        });
+        writeln!(self.writer, "{}", request)?;
+
+        // NOTE: mpv sends events on the same socket. Skip everything
+        // that isn't the response to our request.
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let response: serde_json::Value = match serde_json::from_str(&line) {
+                Ok(response) => response,
+                Err(error) => {
+                    log::warn!("Invalid mpv IPC message: {:?}: {}", line, error);
+                    continue;
+                }
+            };
+            if response["request_id"].as_u64() == Some(self.request_id) {
+                return Ok(response["data"].as_f64());
+            }
+        }
+    }
+}
+
+/// Launch the `episode` in mpv (started at `position` seconds) and
+/// keep track of it in the background.
+///
+/// The episode counts as watched once more than `watched_percent` of
+/// it was played. Otherwise the `Outcome` holds the position to resume
+/// from.
+pub fn play(
+    key: &str,
+    episode: &Path,
+    player: &Player,
+    position: Option<f64>,
+    watched_percent: f64,
+    on_exit: impl FnOnce() + Send + 'static,
+) -> anyhow::Result<Playback> {
+    let socket = socket_path();
+    let mut command = player.command(episode);
+    command.arg(format!("--input-ipc-server={}", socket.display()));
+    if let Some(position) = position {
+        command.arg(format!("--start=+{}", position));
+    }
+    log::info!("Launching mpv: {:?}", command);
+    let child = command
+        .spawn()
+        .map_err(|error| anyhow::anyhow!("Error starting the player `{}`:\n{}", player, error))?;
+    let wait = move || monitor(child, &socket, watched_percent);
+    Ok(Playback::spawn(key, episode, wait, on_exit))
+}
+
+/// Poll the playback position over the IPC `socket` until the mpv
+/// process exits.
+pub fn monitor(mut child: Child, socket: &Path, watched_percent: f64) -> Outcome {
+    let mut time_pos = None;
+    let mut duration = None;
+    match Client::connect_with_timeout(socket, CONNECT_TIMEOUT) {
+        Ok(mut client) => {
+            while let Ok(None) = child.try_wait() {
+                // NOTE: keep the last known values. When mpv quits,
+                // the properties disappear before the socket closes.
+                match client.get_property("time-pos") {
+                    Ok(Some(value)) => time_pos = Some(value),
+                    Ok(None) => {}
+                    Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+                    Err(error) => log::debug!("mpv IPC error: {}", error),
+                }
+                if let Ok(Some(value)) = client.get_property("duration") {
+                    duration = Some(value);
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+        Err(error) => log::error!(
+            "Could not connect to mpv at {}: {}",
+            socket.display(),
+            error
+        ),
+    }
+
+    let status = child.wait();
+    let _ = std::fs::remove_file(socket);
+    log::debug!(
+        "mpv exited: {:?}, time-pos: {:?}, duration: {:?}",
+        status,
+        time_pos,
+        duration
+    );
+    outcome(time_pos, duration, watched_percent)
+}
+
+/// Decide whether the episode was watched based on the last known
+/// position and duration.
+pub fn outcome(time_pos: Option<f64>, duration: Option<f64>, watched_percent: f64) -> Outcome {
+    match (time_pos, duration) {
+        (Some(time_pos), Some(duration)) if duration > 0.0 => {
+            let watched = time_pos / duration * 100.0 >= watched_percent;
+            Outcome {
+                watched,
+                position: (!watched).then_some(time_pos),
+            }
+        }
+        (time_pos, _) => Outcome {
+            watched: false,
+            position: time_pos,
+        },
+    }
+}
+
+/// A unique path for the IPC socket of a new mpv instance.
+fn socket_path() -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("pls-mpv-{}-{}.sock", std::process::id(), count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::net::UnixListener;
+
+    #[test]
+    fn outcome_by_position() {
+        assert_eq!(
+            outcome(Some(0.0), Some(1200.0), 90.0),
+            Outcome {
+                watched: false,
+                position: Some(0.0),
+            }
+        );
+        assert_eq!(
+            outcome(Some(600.0), Some(1200.0), 90.0),
+            Outcome {
+                watched: false,
+                position: Some(600.0),
+            }
+        );
+        assert_eq!(
+            outcome(Some(1200.0), Some(1200.0), 90.0),
+            Outcome {
+                watched: true,
+                position: None,
+            }
+        );
+    }
+
+    #[test]
+    fn outcome_without_duration() {
+        assert_eq!(
+            outcome(Some(600.0), None, 90.0),
+            Outcome {
+                watched: false,
+                position: Some(600.0),
+            }
+        );
+        assert_eq!(
+            outcome(Some(600.0), Some(0.0), 90.0),
+            Outcome {
+                watched: false,
+                position: Some(600.0),
+            }
+        );
+        assert_eq!(outcome(None, None, 90.0), Outcome::default());
+    }
+
+    /// A fake mpv answering `get_property` requests with events and
+    /// replies to other requests mixed in.
+    #[test]
+    fn get_property() {
+        let socket = socket_path();
+        let listener = UnixListener::bind(&socket).unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut lines = BufReader::new(stream).lines();
+
+            let request: serde_json::Value =
+                serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+            assert_eq!(
+                request["command"],
+                serde_json::json!(["get_property", "time-pos"])
+            );
+            let id = request["request_id"].as_u64().unwrap();
+            writeln!(writer, r#"{{"event":"playback-restart"}}"#).unwrap();
+            writeln!(writer, "not json").unwrap();
+            writeln!(
+                writer,
+                r#"{{"data":1.0,"request_id":{},"error":"success"}}"#,
+                id + 100
+            )
+            .unwrap();
+            writeln!(
+                writer,
+                r#"{{"data":12.5,"request_id":{},"error":"success"}}"#,
+                id
+            )
+            .unwrap();
+
+            let request: serde_json::Value =
+                serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+            let id = request["request_id"].as_u64().unwrap();
+            writeln!(writer, r#"{{"event":"seek"}}"#).unwrap();
+            writeln!(
+                writer,
+                r#"{{"request_id":{},"error":"property unavailable"}}"#,
+                id
+            )
+            .unwrap();
+        });
+
+        let mut client = Client::connect(&socket).unwrap();
+        assert_eq!(client.get_property("time-pos").unwrap(), Some(12.5));
+        assert_eq!(client.get_property("duration").unwrap(), None);
+        server.join().unwrap();
+        // NOTE: mpv is gone, either writing or reading fails.
+        assert!(client.get_property("time-pos").is_err());
+        std::fs::remove_file(&socket).unwrap();
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// An external command used to play the episodes instead of the
+/// application associated with the file type.
+///
+/// Set by the `player` key in `pls.toml` (for all shows) or in the
+/// show config (for that show only), e.g.:
+///
+/// `player = ["mpv", "--fs", "{file}"]`
+///
+/// These placeholders are replaced in every argument:
+///
+/// * `{file}`: full path to the episode
+/// * `{name}`: file name of the episode
+/// * `{dir}`: directory containing the episode
+///
+/// The episode's path is appended to the arguments if none of them
+/// contains `{file}`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Player {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl Player {
+    pub fn new(command: &[String]) -> anyhow::Result<Self> {
+        match command {
+            [program, args @ ..] if !program.is_empty() => Ok(Player {
+                program: program.clone(),
+                args: args.to_vec(),
+            }),
+            _ => anyhow::bail!("The `player` command must not be empty"),
+        }
+    }
+
+    /// The command that plays the `episode`.
+    pub fn command(&self, episode: &Path) -> Command {
+        let file = episode.display().to_string();
+        let name = episode
+            .file_name()
+            .map(|f| f.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let dir = episode
+            .parent()
+            .map(|d| d.display().to_string())
+            .unwrap_or_default();
+
+        let mut command = Command::new(&self.program);
+        for arg in &self.args {
+            command.arg(
+                arg.replace("{file}", &file)
+                    .replace("{name}", &name)
+                    .replace("{dir}", &dir),
+            );
+        }
+        if !self.args.iter().any(|arg| arg.contains("{file}")) {
+            command.arg(episode);
+        }
+        command
+    }
+
+    /// Start playing the `episode`.
+    pub fn launch(&self, episode: &Path) -> anyhow::Result<Child> {
+        let mut command = self.command(episode);
+        log::info!("Launching the player: {:?}", command);
+        command
+            .spawn()
+            .map_err(|error| anyhow::anyhow!("Error starting the player `{}`:\n{}", self, error))
+    }
+}
+
+impl std::fmt::Display for Player {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.program)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        Ok(())
+    }
+}
+
+/// How playing an episode ended.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Outcome {
+    /// The episode was watched and the show should move on to the
+    /// next one.
+    pub watched: bool,
+    /// Where the player stopped (in seconds), if it's known and the
+    /// episode wasn't watched.
+    pub position: Option<f64>,
+}
+
+/// An episode playing in a player running in the background.
+#[derive(Debug)]
+pub struct Playback {
+    /// Key of the show being played.
+    pub key: String,
+    /// Full path to the episode being played.
+    pub episode: PathBuf,
+    receiver: Receiver<Outcome>,
+}
+
+impl Playback {
+    /// Wait for the player `child` to exit in a background thread and
+    /// call `on_exit` once it does.
+    ///
+    /// The episode counts as watched if the player ran for at least
+    /// `min_watched` or, if that's not set, if it exited successfully.
+    pub fn start(
+        key: &str,
+        episode: &Path,
+        mut child: Child,
+        min_watched: Option<Duration>,
+        on_exit: impl FnOnce() + Send + 'static,
+    ) -> Self {
+        let started = Instant::now();
+        let wait = move || match child.wait() {
+            Ok(status) => Outcome {
+                watched: was_watched(status, started.elapsed(), min_watched),
+                position: None,
+            },
+            Err(error) => {
+                log::error!("Error waiting for the player: {}", error);
+                Outcome::default()
+            }
+        };
+        Self::spawn(key, episode, wait, on_exit)
+    }
+
+    /// Run `wait` in a background thread and call `on_exit` once it
+    /// returns. `wait` must block until the player exits.
+    pub fn spawn(
+        key: &str,
+        episode: &Path,
+        wait: impl FnOnce() -> Outcome + Send + 'static,
+        on_exit: impl FnOnce() + Send + 'static,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let description = episode.display().to_string();
+        thread::spawn(move || {
+            let outcome = wait();
+            log::info!("Player finished: {}, {:?}", description, outcome);
+            let _ = sender.send(outcome);
+            on_exit();
+        });
+        Playback {
+            key: key.into(),
+            episode: episode.into(),
+            receiver,
+        }
+    }
+
+    /// `None` while the player is still running. Once it exits, how
+    /// the playing ended.
+    pub fn try_finish(&self) -> Option<Outcome> {
+        match self.receiver.try_recv() {
+            Ok(outcome) => Some(outcome),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Outcome::default()),
+        }
+    }
+
+    /// Block until the player exits.
+    pub fn wait(self) -> Outcome {
+        self.receiver.recv().unwrap_or_default()
+    }
+}
+
+fn was_watched(status: ExitStatus, elapsed: Duration, min_watched: Option<Duration>) -> bool {
+    log::debug!("Player exit status: {}, ran for: {:?}", status, elapsed);
+    match min_watched {
+        Some(min_watched) => elapsed >= min_watched,
+        None => status.success(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(command: &[&str]) -> Player {
+        Player::new(&command.iter().map(|&arg| arg.into()).collect::<Vec<_>>()).unwrap()
+    }
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn placeholders() {
+        let episode = Path::new("/shows/Show/Episode 1.mkv");
+        let command =
+            player(&["mpv", "--title={name}", "--fs", "{file}", "--dir={dir}"]).command(episode);
+        assert_eq!(command.get_program(), "mpv");
+        assert_eq!(
+            args(&command),
+            [
+                "--title=Episode 1.mkv",
+                "--fs",
+                "/shows/Show/Episode 1.mkv",
+                "--dir=/shows/Show",
+            ]
+        );
+    }
+
+    /// Without `{file}`, the episode is the last argument.
+    #[test]
+    fn append_file() {
+        let episode = Path::new("/shows/Show/Episode 1.mkv");
+        let command = player(&["vlc", "--meta-title={name}"]).command(episode);
+        assert_eq!(
+            args(&command),
+            ["--meta-title=Episode 1.mkv", "/shows/Show/Episode 1.mkv"]
+        );
+        let command = player(&["vlc"]).command(episode);
+        assert_eq!(args(&command), ["/shows/Show/Episode 1.mkv"]);
+    }
+
+    #[test]
+    fn empty_command() {
+        assert!(Player::new(&[]).is_err());
+        assert!(Player::new(&["".into()]).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Return `true` if the path has one of the supported playlist
+/// extensions: `m3u`, `m3u8` or `pls`.
+pub fn is_playlist(path: &Path) -> bool {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    matches!(extension.as_str(), "m3u" | "m3u8" | "pls")
+}
+
+/// Read the entries of the M3U/M3U8 or PLS playlist at `path`.
+///
+/// The entries are returned as written in the playlist (so they're
+/// either absolute or relative to the playlist's directory), with the
+/// path separators converted to the current platform's ones.
+/// Non-file URLs are skipped.
+pub fn read_playlist(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let bytes = fs::read(path)?;
+    let contents = String::from_utf8_lossy(&bytes);
+    let contents = contents.trim_start_matches('\u{feff}');
+
+    let is_pls = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("pls"));
+    let entries = if is_pls {
+        pls_entries(contents)
+    } else {
+        m3u_entries(contents)
+    };
+
+    Ok(entries
+        .into_iter()
+        .filter_map(|entry| {
+            let entry = match entry.strip_prefix("file://") {
+                Some(file_path) => {
+                    let file_path = percent_decode(file_path);
+                    // NOTE: `file:///C:/...` on Windows
+                    match file_path.as_bytes() {
+                        [b'/', _, b':', ..] => file_path[1..].to_string(),
+                        _ => file_path,
+                    }
+                }
+                None if entry.contains("://") => {
+                    log::warn!("Skipping a non-file playlist entry: `{}`", entry);
+                    return None;
+                }
+                None => entry.to_string(),
+            };
+            Some(PathBuf::from(
+                entry.replace(&['\\', '/'][..], std::path::MAIN_SEPARATOR_STR),
+            ))
+        })
+        .collect())
+}
+
+/// Every line that's not empty or a comment (`#EXTINF` etc.) is an entry.
+fn m3u_entries(contents: &str) -> Vec<&str> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
+/// The `FileN=path` lines ordered by `N`.
+fn pls_entries(contents: &str) -> Vec<&str> {
+    let mut entries = contents
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.trim().split_once('=')?;
+            let index = key.trim().strip_prefix("File")?.parse::<u32>().ok()?;
+            Some((index, value.trim()))
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|(index, _)| *index);
+    entries.into_iter().map(|(_, entry)| entry).collect()
+}
+
+/// Decode the `%XX` escapes in a `file://` URL.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                result.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                result.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write the playlist `contents` into a temporary file called `name`
+    /// and read it.
+    fn read(name: &str, contents: &str) -> Vec<PathBuf> {
+        let dir = crate::util::test_dir(name);
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        let entries = read_playlist(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        entries
+    }
+
+    fn paths(entries: &[&str]) -> Vec<PathBuf> {
+        entries
+            .iter()
+            .map(|entry| PathBuf::from(entry.replace('/', std::path::MAIN_SEPARATOR_STR)))
+            .collect()
+    }
+
+    #[test]
+    fn m3u() {
+        let contents = "\u{feff}#EXTM3U\n\
+            #EXTINF:1320,Show - Episode 1\n\
+            Season 1/Episode 1.mkv\n\
+            \n\
+            #EXTINF:1320,Show - Episode 2\n\
+            Season 1\\Episode 2.mkv\n\
+            http://example.com/stream.mkv\n\
+            /shows/Episode 3.mkv\n";
+        assert_eq!(
+            read("show.m3u8", contents),
+            paths(&[
+                "Season 1/Episode 1.mkv",
+                "Season 1/Episode 2.mkv",
+                "/shows/Episode 3.mkv",
+            ])
+        );
+    }
+
+    #[test]
+    fn pls() {
+        let contents = "[playlist]\n\
+            File2=Episode 2.mkv\n\
+            Title2=Episode 2\n\
+            File10=Episode 10.mkv\n\
+            File1=Episode 1.mkv\n\
+            File3=https://example.com/stream.mkv\n\
+            NumberOfEntries=4\n\
+            Version=2\n";
+        assert_eq!(
+            pls_entries(contents),
+            [
+                "Episode 1.mkv",
+                "Episode 2.mkv",
+                "https://example.com/stream.mkv",
+                "Episode 10.mkv"
+            ]
+        );
+        assert_eq!(
+            read("show.pls", contents),
+            paths(&["Episode 1.mkv", "Episode 2.mkv", "Episode 10.mkv"])
+        );
+    }
+
+    #[test]
+    fn file_urls() {
+        let contents = "file:///shows/Season%201/Episode%201.mkv\n\
+            file:///C:/Shows/Episode%202.mkv\n";
+        assert_eq!(
+            read("urls.m3u", contents),
+            paths(&["/shows/Season 1/Episode 1.mkv", "C:/Shows/Episode 2.mkv"])
+        );
+    }
+
+    #[test]
+    fn percent_escapes() {
+        assert_eq!(percent_decode("Episode%201.mkv"), "Episode 1.mkv");
+        assert_eq!(percent_decode("%C3%A9pisode"), "épisode");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%2"), "%zz%2");
+    }
+
+    #[test]
+    fn extensions() {
+        assert!(is_playlist(Path::new("show.m3u")));
+        assert!(is_playlist(Path::new("show.M3U8")));
+        assert!(is_playlist(Path::new("show.pls")));
+        assert!(!is_playlist(Path::new("show.txt")));
+    }
+}
@@ -0,0 +1,215 @@
+//! The progress of every show: the `next` episode, the position to
+//! resume it from, whether it's finished and its undo history.
+//!
+//! The progress lives in its own file in the data directory (one per
+//! profile), so pls never has to change the hand-edited show configs.
+
+use crate::{
+    config::{self, ConfigError},
+    show::Show,
+    storage,
+};
+
+use std::path::{Path, PathBuf};
+
+use toml_edit::{Document, Item, Table, TableLike};
+
+/// Name of the progress file inside the data directory (or the
+/// directory of a profile).
+pub const PROGRESS_FILE_NAME: &str = "progress.toml";
+
+/// The directory holding the progress and the watch history of the
+/// `profile`: `profiles/<profile>` in the `data_dir` or the `data_dir`
+/// itself without a profile.
+pub fn profile_dir(data_dir: &Path, profile: Option<&str>) -> PathBuf {
+    match profile {
+        Some(profile) => data_dir.join("profiles").join(profile),
+        None => data_dir.to_path_buf(),
+    }
+}
+
+/// Read the progress file from `dir`. Returns an empty document if
+/// there's no progress yet.
+pub fn read(dir: &Path) -> Result<Document, ConfigError> {
+    let path = dir.join(PROGRESS_FILE_NAME);
+    if !path.exists() {
+        return Ok(Document::new());
+    }
+    config::read_document(&path)
+}
+
+/// The saved progress of the show with the given `key`.
+pub fn show_table<'a>(doc: &'a Document, key: &str) -> Option<&'a dyn TableLike> {
+    doc.get("shows")
+        .and_then(|shows| shows.get(key))
+        .and_then(Item::as_table_like)
+}
+
+/// Save the progress of the `show` with the given `key` into the
+/// progress file in `dir`. The file is read again first, so the
+/// progress of the other shows stays as it is on disk.
+///
+/// `known` is the progress file as pls last read or saved it. If the
+/// show's progress on disk is different, someone else (e.g. another
+/// instance of pls) changed it in the meantime and the save is refused
+/// rather than overwriting their change. `known` is updated with the
+/// saved progress.
+pub fn save(dir: &Path, key: &str, show: &Show, known: &mut Document) -> anyhow::Result<()> {
+    let path = dir.join(PROGRESS_FILE_NAME);
+    log::info!("Saving the progress of `{}` into {}", key, path.display());
+    let _lock = storage::lock(dir)?;
+    let mut doc = read(dir)?;
+    if comparable(&doc, key) != comparable(known, key) {
+        anyhow::bail!(
+            "The progress of `{}` in {} was changed by another program since pls read it. Reload to see the change.",
+            key,
+            path.display()
+        );
+    }
+    fill(show_table_mut(&mut doc, key)?, show);
+    storage::write(&path, doc.to_string().trim_start())?;
+    fill(show_table_mut(known, key)?, show);
+    Ok(())
+}
+
+/// Forget the progress of the show with the given `key`.
+pub fn remove(dir: &Path, key: &str) -> anyhow::Result<()> {
+    let path = dir.join(PROGRESS_FILE_NAME);
+    if !path.exists() {
+        return Ok(());
+    }
+    let _lock = storage::lock(dir)?;
+    let mut doc = read(dir)?;
+    let removed = doc
+        .get_mut("shows")
+        .and_then(Item::as_table_like_mut)
+        .and_then(|shows| shows.remove(key))
+        .is_some();
+    if removed {
+        log::info!("Removing the progress of `{}` from {}", key, path.display());
+        storage::write(&path, doc.to_string().trim_start())?;
+    }
+    Ok(())
+}
+
+/// Write the show's progress into its `table`.
+fn fill(table: &mut dyn TableLike, show: &Show) {
+    config::set_value(table, "next", show.next.display().to_string());
+    match show.position {
+        Some(position) => config::set_value(table, "position", position),
+        None => {
+            table.remove("position");
+        }
+    }
+    if show.finished {
+        config::set_value(table, "finished", true);
+    } else {
+        table.remove("finished");
+    }
+    if show.undo.is_empty() {
+        table.remove("undo");
+    } else {
+        let undo = show
+            .undo
+            .iter()
+            .map(|episode| episode.display().to_string())
+            .collect::<toml_edit::Array>();
+        config::set_value(table, "undo", undo);
+    }
+}
+
+/// The saved progress of the show with the given `key` as a list of
+/// keys and values, to compare it regardless of the formatting.
+fn comparable(doc: &Document, key: &str) -> Option<Vec<(String, String)>> {
+    show_table(doc, key).map(|table| {
+        table
+            .iter()
+            .map(|(key, item)| (key.to_string(), item.to_string().trim().to_string()))
+            .collect()
+    })
+}
+
+/// The `[shows.<key>]` table of the progress file, created if missing.
+fn show_table_mut<'a>(doc: &'a mut Document, key: &str) -> anyhow::Result<&'a mut dyn TableLike> {
+    let root = doc.as_table_mut();
+    if !root.contains_key("shows") {
+        let mut shows = Table::new();
+        shows.set_implicit(true);
+        root.insert("shows", Item::Table(shows));
+    }
+    let shows = root
+        .get_mut("shows")
+        .and_then(Item::as_table_like_mut)
+        .ok_or_else(|| anyhow::anyhow!("`shows` must be a table"))?;
+    if !shows.contains_key(key) {
+        shows.insert(key, toml_edit::table());
+    }
+    shows
+        .get_mut(key)
+        .and_then(Item::as_table_like_mut)
+        .ok_or_else(|| anyhow::anyhow!("`shows.{key}` must be a table"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{state::State, util::test_dir};
+
+    use std::fs;
+
+    #[test]
+    fn save_refuses_changed_progress() {
+        let dir = test_dir("progress-conflict");
+        let show_dir = dir.join("show");
+        fs::create_dir(&show_dir).unwrap();
+        for episode in ["Episode 1.mkv", "Episode 2.mkv", "Episode 3.mkv"] {
+            fs::write(show_dir.join(episode), "").unwrap();
+        }
+        let config_path = dir.join("show.toml");
+        fs::write(
+            &config_path,
+            format!(
+                "name = \"Show\"\ndirectory = {:?}\n",
+                show_dir.display().to_string()
+            ),
+        )
+        .unwrap();
+        let data_dir = dir.join("data");
+        let mut known = Document::new();
+        let mut show = State::load_show_file(&config_path, "show", None, &known).unwrap();
+        save(&data_dir, "show", &show, &mut known).unwrap();
+        show.advance_to_next_episode();
+        save(&data_dir, "show", &show, &mut known).unwrap();
+        assert_eq!(
+            show_table(&read(&data_dir).unwrap(), "show")
+                .and_then(|table| table.get("next"))
+                .and_then(Item::as_str),
+            Some("Episode 2.mkv")
+        );
+
+        // NOTE: e.g. another instance of pls advanced the show too.
+        let path = data_dir.join(PROGRESS_FILE_NAME);
+        let changed = "[shows.show]\nnext = \"Episode 3.mkv\"\n";
+        fs::write(&path, changed).unwrap();
+        show.advance_to_next_episode();
+        let error = save(&data_dir, "show", &show, &mut known).unwrap_err();
+        assert!(
+            error.to_string().contains("changed by another program"),
+            "{error}"
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), changed);
+
+        // The progress of the other shows doesn't matter.
+        let mut known = read(&data_dir).unwrap();
+        fs::write(
+            &path,
+            format!("{changed}\n[shows.other]\nnext = \"a.mkv\"\n"),
+        )
+        .unwrap();
+        save(&data_dir, "show", &show, &mut known).unwrap();
+        let doc = read(&data_dir).unwrap();
+        assert!(show_table(&doc, "other").is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,46 +1,608 @@
-use std::path::{Path, PathBuf};
+use crate::{episode::EpisodeNumber, filter::EpisodeFilter, player::Player};
 
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use humanesort::HumaneOrder;
+
+/// A series of files played one after another.
 #[derive(Debug)]
 pub struct Show {
     pub name: String,
+    /// The file the show is defined in: its own `<key>.toml` file or
+    /// `pls.toml` for shows defined inline.
+    pub config_path: PathBuf,
+    /// The directory holding all the episodes. For shows loaded from
+    /// a playlist file, this is the playlist's directory.
     pub dir: PathBuf,
+    /// Path to the episode to play next, relative to `dir`.
     pub next: PathBuf,
+    /// Where to resume playing the `next` episode from (in seconds).
+    pub position: Option<f64>,
+    /// The last episode was watched. `next` stays at the last episode.
+    pub finished: bool,
+    /// The earlier `next` episodes (relative to `dir`), most recent
+    /// last. Used to undo advancing the show.
+    pub undo: Vec<PathBuf>,
+    /// The M3U/PLS playlist listing the episodes. When set, the
+    /// episodes come from the playlist rather than from `dir`.
+    pub playlist_file: Option<PathBuf>,
+    /// Decides which files in `dir` are episodes.
+    pub filter: EpisodeFilter,
+    /// The order in which the episodes are played.
+    pub sort: Sort,
+    /// Explicit episode order (relative to `dir`) used by `Sort::Playlist`.
+    pub playlist: Vec<PathBuf>,
+    /// The player for this show. Overrides the one in `pls.toml`.
+    pub player: Option<Player>,
+    /// How long the player must run for the episode to count as
+    /// watched. Overrides the one in `pls.toml`.
+    pub min_watched: Option<Duration>,
+    /// Track the playback position with mpv. Overrides the
+    /// `mpv_ipc` value in `pls.toml`.
+    pub mpv_ipc: Option<bool>,
+    /// How much of the episode (in percent) must be played in mpv for
+    /// it to count as watched. Overrides the one in `pls.toml`.
+    pub watched_percent: Option<f64>,
+    /// The episodes found by the last scan (see `refresh_episodes`).
+    pub index: EpisodeIndex,
+}
+
+/// The show's episodes as they were on disk the last time they were
+/// scanned. Looking at the show (e.g. drawing the window every frame)
+/// only goes through the index, never through the filesystem.
+#[derive(Debug, Default)]
+pub struct EpisodeIndex {
+    /// Full paths to all the episodes in the order they should be
+    /// played.
+    episodes: Vec<PathBuf>,
+    /// Index of every episode in `episodes`.
+    positions: HashMap<PathBuf, usize>,
+    /// The scanned directories (or the playlist file) and their
+    /// modification times. Adding, removing or renaming a file changes
+    /// the time of its directory.
+    mtimes: Vec<(PathBuf, Option<SystemTime>)>,
+    /// The playlist file's entries as written, by the full path of
+    /// their episode. Empty for directories.
+    entries: HashMap<PathBuf, PathBuf>,
+}
+
+impl EpisodeIndex {
+    /// Where the `episode` (a full path) is in the show.
+    pub fn position(&self, episode: &Path) -> Option<usize> {
+        self.positions.get(episode).copied()
+    }
+
+    /// The index is out of date if any of the scanned directories
+    /// changed since. Only reads the metadata of the directories, not
+    /// their contents.
+    pub fn is_stale(&self) -> bool {
+        self.mtimes
+            .iter()
+            .any(|(path, mtime)| modified(path) != *mtime)
+    }
+}
+
+/// The modification time of the file or directory at `path`.
+fn modified(path: &Path) -> Option<SystemTime> {
+    path.metadata().and_then(|m| m.modified()).ok()
 }
 
 impl Show {
+    /// The show's playlist file or its directory.
+    pub fn location(&self) -> &Path {
+        self.playlist_file.as_deref().unwrap_or(&self.dir)
+    }
+
+    /// Full path to the episode to play next.
     pub fn current_episode(&self) -> PathBuf {
         self.dir.join(&self.next)
     }
 
+    /// Move `next` to the episode following the current one. When
+    /// the current episode is the last one, the show is `finished`
+    /// instead.
     pub fn advance_to_next_episode(&mut self) {
-        let eps = self.episodes();
-        let mut iter = eps.iter();
-        let current_episode = self.current_episode();
-        // Find the current episode
-        iter.find(|episode| episode == &&current_episode);
-        // Get the next one:
-        let next_episode = iter.next();
-        if let Some(next) = next_episode {
-            let full = PathBuf::from(next);
-            let stripped = full.strip_prefix(&self.dir);
-            self.next = stripped.map(Path::to_path_buf).unwrap_or(full);
+        let current = self.index.position(&self.current_episode());
+        let next_episode = current.and_then(|index| self.episodes().get(index + 1).cloned());
+        match next_episode {
+            Some(next) => self.set_next_episode(&next),
+            None if current.is_some() => {
+                self.finished = true;
+                self.position = None;
+            }
+            None => {}
+        }
+    }
+
+    /// If the show is `finished` but new episodes were added after the
+    /// last watched one, move on to the first of them. Returns `true`
+    /// if it did.
+    pub fn resume_if_new_episodes(&mut self) -> bool {
+        if !self.finished {
+            return false;
+        }
+        let current = self.index.position(&self.current_episode());
+        if let Some(next) = current.and_then(|index| self.episodes().get(index + 1).cloned()) {
+            self.set_next_episode(&next);
+            return true;
+        }
+        false
+    }
+
+    /// Start watching the show again from the first episode.
+    pub fn restart(&mut self) {
+        if let Some(first) = self.episodes().first() {
+            self.set_next_episode(&first.clone());
         }
     }
 
+    /// Set `next` to the given episode (either a full path or one
+    /// relative to `dir`) and start it from the beginning. For shows
+    /// with a playlist file, `next` is the episode's entry as written
+    /// in the playlist.
+    pub fn set_next_episode(&mut self, episode: &Path) {
+        let full = PathBuf::from(episode);
+        self.next = match self.index.entries.get(&full) {
+            Some(entry) => entry.clone(),
+            None => match full.strip_prefix(&self.dir) {
+                Ok(stripped) if self.playlist_file.is_none() => stripped.to_path_buf(),
+                _ => full,
+            },
+        };
+        self.position = None;
+        self.finished = false;
+    }
+
+    /// Move `next` to the episode before the current one. The
+    /// counterpart of `advance_to_next_episode`: a `finished` show
+    /// goes back to its last episode. Does nothing when the current
+    /// episode is the first one.
+    pub fn rewind_to_previous_episode(&mut self) {
+        if self.finished {
+            self.finished = false;
+        } else if let Some(previous) = self.previous_episode() {
+            self.set_next_episode(&previous);
+        }
+    }
+
+    /// An episode to take the place of the `next` one when its file is
+    /// gone (renamed or deleted): the only one with the same season
+    /// and episode number, the first one numbered after it or else the
+    /// one sorted (humanely) right where the missing one was. `None`
+    /// if the show has no episodes left.
+    ///
+    /// Only episodes that exist are considered, the entries of a
+    /// playlist may be gone too.
+    pub fn find_replacement(&self) -> Option<PathBuf> {
+        let episodes = self
+            .episodes()
+            .iter()
+            .filter(|episode| episode.is_file())
+            .collect::<Vec<_>>();
+        if let Some(number) = EpisodeNumber::parse(&self.next) {
+            let mut matching = episodes
+                .iter()
+                .filter(|episode| self.episode_number(episode) == Some(number));
+            if let (Some(episode), None) = (matching.next(), matching.next()) {
+                return Some(episode.to_path_buf());
+            }
+            let later = episodes.iter().find(|episode| {
+                self.episode_number(episode)
+                    .is_some_and(|n| n.season == number.season && n > number)
+            });
+            if let Some(episode) = later {
+                return Some(episode.to_path_buf());
+            }
+        }
+        let missing = self.next.display().to_string();
+        episodes
+            .iter()
+            .find(|episode| {
+                let relative = episode.strip_prefix(&self.dir).unwrap_or(episode);
+                relative.display().to_string().humane_cmp(&missing) == Ordering::Greater
+            })
+            .or_else(|| episodes.last())
+            .map(|episode| episode.to_path_buf())
+    }
+
+    /// Remember the current `next` episode so the following change can
+    /// be undone. Only the last `limit` changes are kept.
+    pub fn push_undo(&mut self, limit: usize) {
+        self.undo.push(self.next.clone());
+        if self.undo.len() > limit {
+            self.undo.drain(..self.undo.len() - limit);
+        }
+    }
+
+    /// Return `next` to the episode it was before the last change.
+    /// Returns `false` if there's nothing to undo.
+    pub fn undo_next_episode(&mut self) -> bool {
+        match self.undo.pop() {
+            Some(previous) => {
+                self.set_next_episode(&previous);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// How many episodes are left to watch, counting the `next` one.
+    pub fn unwatched_count(&self) -> usize {
+        if self.finished {
+            return 0;
+        }
+        let count = self.episodes().len();
+        match self.index.position(&self.current_episode()) {
+            Some(index) => count - index,
+            None => count,
+        }
+    }
+
+    /// Full path to the episode before the current one, i.e. the one
+    /// watched last.
     pub fn previous_episode(&self) -> Option<PathBuf> {
-        let eps = self.episodes();
-        let current_episode = self.current_episode();
-        let current_episode_index = eps.iter().position(|episode| episode == &current_episode);
-        if let Some(index) = current_episode_index
-            && index > 0
-        {
-            let previous_episode = &eps[index - 1];
-            return Some(self.dir.join(previous_episode));
+        match self.index.position(&self.current_episode()) {
+            Some(index) if index > 0 => Some(self.dir.join(&self.episodes()[index - 1])),
+            _ => None,
         }
-        None
     }
 
-    pub fn episodes(&self) -> Vec<PathBuf> {
-        crate::util::all_paths_in_dir(&self.dir)
+    /// Full paths to all the episodes of the show in the order they
+    /// should be played (see `Sort`). These come from the index, call
+    /// `refresh_episodes` to pick up the changes on disk.
+    pub fn episodes(&self) -> &[PathBuf] {
+        &self.index.episodes
+    }
+
+    /// Scan the show's directory (or playlist file) for its episodes
+    /// again.
+    pub fn refresh_episodes(&mut self) {
+        self.index = self.scan_episodes();
+    }
+
+    /// Scan the episodes again if any of them were added or removed
+    /// since the last scan. Returns `true` if it did.
+    pub fn refresh_episodes_if_stale(&mut self) -> bool {
+        if self.index.is_stale() {
+            log::debug!("The episodes of `{}` changed on disk", self.name);
+            self.refresh_episodes();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Find all the episodes on disk and sort them.
+    fn scan_episodes(&self) -> EpisodeIndex {
+        let mut entries = HashMap::new();
+        let (mut episodes, scanned) = match &self.playlist_file {
+            Some(playlist_file) => match crate::playlist::read_playlist(playlist_file) {
+                Ok(playlist) => {
+                    let episodes = playlist
+                        .into_iter()
+                        .map(|entry| {
+                            let episode = self.dir.join(&entry);
+                            entries.entry(episode.clone()).or_insert(entry);
+                            episode
+                        })
+                        .collect();
+                    (episodes, vec![playlist_file.clone()])
+                }
+                Err(error) => {
+                    log::error!(
+                        "Could not read playlist `{}`: {}",
+                        playlist_file.display(),
+                        error
+                    );
+                    (vec![], vec![playlist_file.clone()])
+                }
+            },
+            None => crate::util::scan_dir(&self.dir, &self.filter),
+        };
+        // NOTE: the sorts are stable so they keep the original order
+        // (humane for directories, as listed for playlist files) for
+        // ties.
+        match self.sort {
+            Sort::Humane => {
+                if self.playlist_file.is_some() {
+                    crate::util::humane_sort(&mut episodes);
+                }
+            }
+            Sort::Name => episodes.sort(),
+            Sort::Mtime => episodes
+                .sort_by_cached_key(|episode| episode.metadata().and_then(|m| m.modified()).ok()),
+            Sort::Episode => {
+                // NOTE: the numbers only order the episodes within their
+                // directory, the directories stay in order. Otherwise
+                // e.g. `Part 1/Episode 1` and `Part 2/Episode 1` would
+                // take turns.
+                let mut dirs = HashMap::new();
+                for episode in &episodes {
+                    let count = dirs.len();
+                    dirs.entry(episode.parent().map(Path::to_path_buf))
+                        .or_insert(count);
+                }
+                episodes.sort_by_cached_key(|episode| {
+                    let number = self.episode_number(episode);
+                    let dir = dirs[&episode.parent().map(Path::to_path_buf)];
+                    (dir, number.is_none(), number)
+                });
+            }
+            Sort::Playlist => episodes.sort_by_cached_key(|episode| {
+                self.playlist
+                    .iter()
+                    .position(|entry| &self.dir.join(entry) == episode)
+                    .unwrap_or(usize::MAX)
+            }),
+        }
+        let mtimes = scanned
+            .into_iter()
+            .map(|path| {
+                let mtime = modified(&path);
+                (path, mtime)
+            })
+            .collect();
+        let positions = episodes
+            .iter()
+            .enumerate()
+            .map(|(index, episode)| (episode.clone(), index))
+            .collect();
+        EpisodeIndex {
+            episodes,
+            positions,
+            mtimes,
+            entries,
+        }
+    }
+
+    /// The season and episode number parsed from the episode's path.
+    pub fn episode_number(&self, episode: &Path) -> Option<EpisodeNumber> {
+        EpisodeNumber::parse(episode.strip_prefix(&self.dir).unwrap_or(episode))
+    }
+}
+
+/// The order in which the episodes of a show are played. Set by the
+/// `sort` key in the show config.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Sort {
+    /// Humane sort of the paths (i.e. `2` goes before `10`).
+    Humane,
+    /// Plain lexicographic sort of the paths.
+    Name,
+    /// Oldest modification time first.
+    Mtime,
+    /// By the season and episode numbers parsed from the file names.
+    /// Files without a number go after them.
+    #[default]
+    Episode,
+    /// In the order of the show's `playlist` entries (or the
+    /// playlist file). Files not in the playlist go after them.
+    Playlist,
+}
+
+impl std::str::FromStr for Sort {
+    type Err = anyhow::Error;
+
+    fn from_str(sort_str: &str) -> Result<Self, Self::Err> {
+        match sort_str {
+            "humane" => Ok(Sort::Humane),
+            "name" => Ok(Sort::Name),
+            "mtime" => Ok(Sort::Mtime),
+            "episode" => Ok(Sort::Episode),
+            "playlist" => Ok(Sort::Playlist),
+            _ => anyhow::bail!(
+                "Unknown sort: `{sort_str}`. Expected one of: humane, name, mtime, episode, playlist"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Sort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        use Sort::*;
+        let s = match self {
+            Humane => "humane",
+            Name => "name",
+            Mtime => "mtime",
+            Episode => "episode",
+            Playlist => "playlist",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{state::State, util::test_dir};
+
+    use std::fs;
+
+    /// Load a show with the episode `files` from a temporary directory
+    /// for the test called `name`. `config` is added to its config.
+    fn load(name: &str, files: &[&str], config: &str) -> (PathBuf, Show) {
+        let dir = test_dir(name);
+        let show_dir = dir.join("show");
+        for file in files {
+            let path = show_dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let config_path = dir.join("show.toml");
+        fs::write(
+            &config_path,
+            format!(
+                "name = \"Show\"\ndirectory = {:?}\n{config}",
+                show_dir.display().to_string()
+            ),
+        )
+        .unwrap();
+        let show =
+            State::load_show_file(&config_path, "show", None, &toml_edit::Document::new()).unwrap();
+        (dir, show)
+    }
+
+    /// The episodes of the `show` relative to its directory.
+    fn episodes(show: &Show) -> Vec<String> {
+        show.episodes()
+            .iter()
+            .map(|episode| {
+                let relative = episode.strip_prefix(&show.dir).unwrap();
+                relative.display().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sort_by_episode() {
+        let (dir, show) = load(
+            "sort-flat",
+            &["Show 10.mkv", "Extra.mkv", "Show 2.mkv", "Show 1.mkv"],
+            "",
+        );
+        assert_eq!(
+            episodes(&show),
+            ["Show 1.mkv", "Show 2.mkv", "Show 10.mkv", "Extra.mkv"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+
+        let (dir, show) = load(
+            "sort-seasons",
+            &[
+                "Season 10/E01.mkv",
+                "Season 2/E01.mkv",
+                "Season 1/E02.mkv",
+                "Season 1/E01.mkv",
+            ],
+            "",
+        );
+        assert_eq!(
+            episodes(&show),
+            [
+                "Season 1/E01.mkv",
+                "Season 1/E02.mkv",
+                "Season 2/E01.mkv",
+                "Season 10/E01.mkv",
+            ]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Directories that aren't seasons are played one after another.
+    #[test]
+    fn sort_by_episode_in_other_directories() {
+        let files = [
+            "Part 2/Episode 01.mkv",
+            "Part 1/Episode 03.mkv",
+            "Part 1/Episode 01.mkv",
+            "Part 2/Episode 03.mkv",
+            "Part 1/Episode 02.mkv",
+            "Part 2/Episode 02.mkv",
+        ];
+        let (dir, show) = load("sort-parts", &files, "");
+        assert_eq!(
+            episodes(&show),
+            [
+                "Part 1/Episode 01.mkv",
+                "Part 1/Episode 02.mkv",
+                "Part 1/Episode 03.mkv",
+                "Part 2/Episode 01.mkv",
+                "Part 2/Episode 02.mkv",
+                "Part 2/Episode 03.mkv",
+            ]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// `next` is the playlist entry as written, even if it's an
+    /// absolute path into the playlist's directory.
+    #[test]
+    fn next_is_playlist_entry() {
+        let dir = test_dir("playlist-next");
+        fs::create_dir_all(dir.join("show")).unwrap();
+        for file in ["show/E1.mkv", "show/E2.mkv", "E3.mkv"] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        let absolute = dir.canonicalize().unwrap().join("E3.mkv");
+        let playlist_file = dir.join("list.m3u");
+        fs::write(
+            &playlist_file,
+            format!("show/E1.mkv\nshow/E2.mkv\n{}\n", absolute.display()),
+        )
+        .unwrap();
+        let config_path = dir.join("show.toml");
+        fs::write(
+            &config_path,
+            format!(
+                "name = \"Show\"\nplaylist = {:?}\n",
+                playlist_file.display().to_string()
+            ),
+        )
+        .unwrap();
+        let mut show =
+            State::load_show_file(&config_path, "show", None, &toml_edit::Document::new()).unwrap();
+        assert_eq!(show.next, Path::new("show/E1.mkv"));
+        show.advance_to_next_episode();
+        assert_eq!(show.next, Path::new("show/E2.mkv"));
+        show.advance_to_next_episode();
+        assert_eq!(show.next, absolute);
+        assert_eq!(show.index.position(&show.current_episode()), Some(2));
+        show.restart();
+        assert_eq!(show.next, Path::new("show/E1.mkv"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn find_replacement() {
+        let files = [
+            "Show S01E01.mkv",
+            "Show S01E02 720p.mkv",
+            "Show S01E05.mkv",
+            "Show S02E01.mkv",
+        ];
+        let (dir, mut show) = load("replacement", &files, "");
+        let replacement = |show: &mut Show, next: &str| {
+            show.next = next.into();
+            show.find_replacement().map(|episode| {
+                let relative = episode.strip_prefix(&show.dir).unwrap();
+                relative.display().to_string()
+            })
+        };
+        // The same season and episode number.
+        assert_eq!(
+            replacement(&mut show, "Show S01E02.mkv").as_deref(),
+            Some("Show S01E02 720p.mkv")
+        );
+        // The first one numbered after it in the same season.
+        assert_eq!(
+            replacement(&mut show, "Show S01E03.mkv").as_deref(),
+            Some("Show S01E05.mkv")
+        );
+        // Sorted right where the missing one was.
+        assert_eq!(
+            replacement(&mut show, "Show S01E10.mkv").as_deref(),
+            Some("Show S02E01.mkv")
+        );
+        assert_eq!(
+            replacement(&mut show, "Show Extra.mkv").as_deref(),
+            Some("Show S01E01.mkv")
+        );
+        assert_eq!(
+            replacement(&mut show, "Zzz.mkv").as_deref(),
+            Some("Show S02E01.mkv")
+        );
+        // Nothing left.
+        for file in files {
+            fs::remove_file(show.dir.join(file)).unwrap();
+        }
+        assert_eq!(replacement(&mut show, "Show S01E02.mkv"), None);
+        fs::remove_dir_all(dir).unwrap();
     }
 }
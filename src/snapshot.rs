@@ -0,0 +1,105 @@
+//! The episodes pls knew about the last time it ran, used to point out
+//! the ones added since.
+
+use crate::{show::Show, storage};
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use toml_edit::{Array, Document, Item, Table};
+
+/// Name of the known episodes file inside the data directory.
+pub const SNAPSHOT_FILE_NAME: &str = "known_episodes.toml";
+
+/// The episodes of every show (relative to the show's directory)
+/// indexed by the show's key.
+pub type Snapshot = HashMap<String, Vec<PathBuf>>;
+
+/// Read the snapshot from `data_dir`. Returns an empty one if there's
+/// no snapshot yet.
+pub fn read(data_dir: &Path) -> anyhow::Result<Snapshot> {
+    let path = data_dir.join(SNAPSHOT_FILE_NAME);
+    if !path.exists() {
+        return Ok(Snapshot::new());
+    }
+    let doc = crate::config::read_document(&path)?;
+    let mut snapshot = Snapshot::new();
+    if let Some(shows) = doc.get("shows").and_then(Item::as_table_like) {
+        for (key, show) in shows.iter() {
+            let episodes = show
+                .get("episodes")
+                .and_then(Item::as_array)
+                .map(|episodes| {
+                    episodes
+                        .iter()
+                        .filter_map(|e| e.as_str().map(PathBuf::from))
+                        .collect()
+                })
+                .unwrap_or_default();
+            snapshot.insert(key.into(), episodes);
+        }
+    }
+    Ok(snapshot)
+}
+
+/// Write the current episodes of all the `shows` into `data_dir`.
+pub fn write(data_dir: &Path, shows: &HashMap<String, Show>) -> anyhow::Result<()> {
+    let path = data_dir.join(SNAPSHOT_FILE_NAME);
+    log::debug!("Writing the known episodes to: {}", path.display());
+    let mut keys = shows.keys().collect::<Vec<_>>();
+    keys.sort();
+    let mut shows_table = Table::new();
+    shows_table.set_implicit(true);
+    for key in keys {
+        let show = &shows[key];
+        let episodes = show
+            .episodes()
+            .iter()
+            .map(|episode| {
+                episode
+                    .strip_prefix(&show.dir)
+                    .unwrap_or(episode)
+                    .display()
+                    .to_string()
+            })
+            .collect::<Array>();
+        let mut table = Table::new();
+        table["episodes"] = toml_edit::value(episodes);
+        shows_table[key.as_str()] = Item::Table(table);
+    }
+    let mut doc = Document::new();
+    doc["shows"] = Item::Table(shows_table);
+    let _lock = storage::lock(data_dir)?;
+    storage::write(&path, doc.to_string().trim_start())?;
+    Ok(())
+}
+
+/// The episodes of every show (full paths) that aren't in the
+/// `snapshot`. Shows missing from the snapshot (i.e. new shows) have
+/// no new episodes.
+pub fn new_episodes(
+    snapshot: &Snapshot,
+    shows: &HashMap<String, Show>,
+) -> HashMap<String, Vec<PathBuf>> {
+    let mut result = HashMap::new();
+    for (key, show) in shows {
+        let Some(known) = snapshot.get(key) else {
+            continue;
+        };
+        let new = show
+            .episodes()
+            .iter()
+            .filter(|episode| {
+                let relative = episode.strip_prefix(&show.dir).unwrap_or(episode);
+                !known.iter().any(|known| known == relative)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        if !new.is_empty() {
+            result.insert(key.clone(), new);
+        }
+    }
+    result
+}
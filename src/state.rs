@@ -1,49 +1,160 @@
-use crate::show::Show;
+pub use crate::config::Version;
+
+use crate::{
+    config::{self, Config, ConfigError, Location, ShowConfig},
+    editor::ShowEditor,
+    filter::EpisodeFilter,
+    history::{self, Play},
+    player::{Outcome, Playback, Player},
+    progress,
+    show::{EpisodeIndex, Show, Sort},
+    snapshot,
+    watch::Watcher,
+};
 
 use std::{
     collections::HashMap,
     ffi::OsStr,
-    fmt, fs,
+    fs,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
 use toml_edit::Document;
 
+/// The loaded config directory: the contents of `pls.toml` and all the
+/// shows defined next to it.
 #[derive(Debug)]
 pub struct State {
     pub config_version: Version,
     pub selected_key: String,
+    /// Keys of all the shows in the order they should be listed in.
     pub ordered_keys: Vec<String>,
     pub config: Document,
+    /// Full path to `pls.toml`.
     pub config_path: PathBuf,
+    /// The directory with the progress, watch history and known
+    /// episodes (see `crate::data_dir`).
+    pub data_dir: PathBuf,
+    /// The progress file of the current profile as pls last read or
+    /// saved it. Used to notice changes made by someone else (see
+    /// `progress::save`).
+    pub saved_progress: Document,
+    /// The profile whose progress and history are used. `None` for
+    /// the shows' own progress.
+    pub profile: Option<String>,
+    /// Loaded for a command that only looks at the shows: nothing is
+    /// migrated, moved or saved. Missing `next` episodes are still
+    /// replaced (and added to `repairs`) but only in memory.
+    pub read_only: bool,
+    /// All the profiles listed in `pls.toml`.
+    pub profiles: Vec<String>,
+    /// All the successfully loaded shows, indexed by their key (the
+    /// file stem of the show's config file or the name of its
+    /// `[shows.<key>]` table).
     pub shows: HashMap<String, Show>,
+    /// Episodes (full paths) added since pls last ran, indexed by the
+    /// show's key.
+    pub new_episodes: HashMap<String, Vec<PathBuf>>,
+    /// The shows that failed to load, sorted by their key.
+    pub broken_shows: Vec<BrokenShow>,
+    /// The player used for shows that don't specify their own.
+    pub player: Option<Player>,
+    /// How long the player must run for the episode to count as
+    /// watched for shows that don't specify their own.
+    pub min_watched: Option<Duration>,
+    /// Track the playback position with mpv for shows that don't
+    /// specify otherwise.
+    pub mpv_ipc: bool,
+    /// How much of the episode (in percent) must be played in mpv for
+    /// it to count as watched for shows that don't specify their own.
+    pub watched_percent: Option<f64>,
+    /// Episodes currently playing in a player.
+    pub playing: Vec<Playback>,
     pub error: Option<String>,
+    /// A message for the user that isn't an error, e.g. that another
+    /// launch of pls found the window open.
+    pub notice: Option<String>,
     pub about_window_is_open: bool,
+    pub history_window_is_open: bool,
+    /// The watch history, loaded when the History window is opened.
+    pub history: Vec<Play>,
+    pub episodes_window_is_open: bool,
+    /// Episodes selected in the Episodes window (full paths).
+    pub selected_episodes: Vec<PathBuf>,
+    /// The shows whose missing `next` episode was replaced. Kept until
+    /// the user sees them.
+    pub repairs: Vec<Repair>,
+    /// Watches the config and show directories for changes. Only
+    /// running when started with `start_watching`.
+    pub watcher: Option<Watcher>,
+    /// The show being created or edited in the show editor window.
+    pub show_editor: Option<ShowEditor>,
 }
 
 impl State {
-    pub fn new(config_path: &Path) -> anyhow::Result<Self> {
-        let toml = fs::read_to_string(config_path)?;
-        let doc = toml.parse::<Document>()?;
+    /// Load `pls.toml` and all the shows from the given config
+    /// directory with the progress of the given `profile` saved in
+    /// `data_dir`. See `read_only`.
+    pub fn from_config_dir(
+        config_dir: &Path,
+        data_dir: &Path,
+        profile: Option<&str>,
+        read_only: bool,
+    ) -> anyhow::Result<Self> {
+        let config_path = config_dir.join(crate::CONFIG_FILE_NAME);
+        let config_path = config_path
+            .canonicalize()
+            .map_err(|error| ConfigError::Io {
+                path: config_path,
+                error,
+            })?;
+        Self::new(&config_path, data_dir, profile, read_only)
+    }
 
-        let shows: HashMap<String, Show> = config_path
-            .parent()
-            // TODO: we'll probably want to actually process the `load_shows` error:
-            .and_then(|show_dir| State::load_shows(show_dir).ok())
-            .unwrap_or_default();
+    /// Load the config file at `config_path` (i.e. `pls.toml`) and all
+    /// the shows in the same directory with the progress of the given
+    /// `profile` saved in `data_dir`. See `read_only`.
+    pub fn new(
+        config_path: &Path,
+        data_dir: &Path,
+        profile: Option<&str>,
+        read_only: bool,
+    ) -> anyhow::Result<Self> {
+        let mut doc = config::read_document(config_path)?;
+
+        let config_version = config::version(&doc)?;
+        if config_version < Version::CURRENT && !read_only {
+            if let Some(config_dir) = config_path.parent() {
+                config::migrate(config_dir, data_dir, config_version)?;
+            }
+            doc = config::read_document(config_path)?;
+        }
+        let config_version = config::version(&doc)?;
+        log::info!("Config version: {}", config_version);
+
+        let config = Config::from_doc(&doc)?;
+        if let Some(profile) = profile
+            && !config.profiles.iter().any(|p| p == profile)
+        {
+            anyhow::bail!(
+                "Unknown profile `{profile}`. Add it to `profiles` in {}",
+                config_path.display()
+            );
+        }
+        if let Some(config_dir) = config_path.parent()
+            && !read_only
+        {
+            move_data_files(config_dir, data_dir);
+        }
+        let saved_progress = progress::read(&progress::profile_dir(data_dir, profile))?;
+        let (shows, broken_shows) = State::load_shows(config_path, &doc, profile, &saved_progress)?;
         log::debug!("Loaded shows: {:#?}", shows);
+        log::debug!("Broken shows: {:#?}", broken_shows);
 
         // NOTE: Load the `ordering` if it exists in `pls.toml` and
         // use that as the main order in which the shows are listed.
-        let mut ordered_keys = doc
-            .get("ordering")
-            .and_then(toml_edit::Item::as_array)
-            .map(|a| {
-                a.iter()
-                    .filter_map(|i| i.as_str().map(String::from))
-                    .collect::<Vec<_>>()
-            })
-            .unwrap_or_default();
+        let mut ordered_keys = config.ordering;
         // Since `ordering` is optional and may not contain all (or
         // any!) of the shows, add in any other shows that we know
         // about.
@@ -56,186 +167,1201 @@ impl State {
         }
         log::debug!("Ordered keys: {:#?}", ordered_keys);
 
-        let first_key = ordered_keys.first().cloned().unwrap_or_default();
-        log::debug!("First key: {:?}", first_key);
+        let player = config
+            .player
+            .map(|command| Player::new(&command))
+            .transpose()?;
+        log::debug!("Player: {:?}", player);
+        let min_watched = config.min_watched;
+        let mpv_ipc = config.mpv_ipc.unwrap_or(false);
+        let watched_percent = config.watched_percent;
 
-        let version: Option<Version> = doc
-            .get("version")
-            .and_then(toml_edit::Item::as_str)
-            .and_then(Version::from_str);
+        let new_episodes = State::update_snapshot(data_dir, &shows, read_only);
+        log::debug!("New episodes: {:#?}", new_episodes);
 
-        let config_version = version.unwrap_or_else(|| {
-            let fallback = Version::fallback();
-            log::warn!(
-                "Unknown or no config version specified. Falling back to: {}",
-                fallback
-            );
-            fallback
-        });
-        log::info!("Config version: {}", config_version);
+        let first_key = ordered_keys.first().cloned().unwrap_or_default();
+        log::debug!("First key: {:?}", first_key);
 
-        Ok(State {
+        let mut state = State {
             config_version,
             selected_key: first_key,
             ordered_keys,
             config_path: config_path.into(),
+            data_dir: data_dir.into(),
+            saved_progress,
+            profile: profile.map(String::from),
+            read_only,
+            profiles: config.profiles,
             config: doc,
             shows,
+            new_episodes,
+            broken_shows,
+            player,
+            min_watched,
+            mpv_ipc,
+            watched_percent,
+            playing: vec![],
             error: None,
+            notice: None,
             about_window_is_open: false,
-        })
+            history_window_is_open: false,
+            history: vec![],
+            episodes_window_is_open: false,
+            selected_episodes: vec![],
+            show_editor: None,
+            watcher: None,
+            repairs: vec![],
+        };
+        let keys = state.ordered_keys.clone();
+        // NOTE: the shows without any saved progress (i.e. new shows or
+        // ones from before there was a progress file) start where their
+        // config says.
+        for key in &keys {
+            if !read_only
+                && state.shows.contains_key(key)
+                && progress::show_table(&state.saved_progress, key).is_none()
+            {
+                log::info!("Saving the initial progress of show `{}`", key);
+                state.save_progress(key)?;
+            }
+        }
+        for key in keys {
+            state.repair_next_episode(&key)?;
+            state.resume_if_new_episodes(&key)?;
+        }
+        Ok(state)
     }
 
+    /// Replace the state with a fresh copy loaded from the disk. The
+    /// running players, the repairs and what the window shows are
+    /// kept.
     pub fn reload_config(&mut self) -> anyhow::Result<()> {
-        let new_config = Self::new(&self.config_path)?;
-        *self = new_config;
+        let new_config = Self::new(
+            &self.config_path,
+            &self.data_dir,
+            self.profile.as_deref(),
+            self.read_only,
+        )?;
+        let mut old = std::mem::replace(self, new_config);
+        // NOTE: keep waiting for the players that are still running.
+        self.playing = old.playing;
+        self.watcher = old.watcher;
+        old.repairs.append(&mut self.repairs);
+        self.repairs = old.repairs;
+        self.update_watches();
+        // NOTE: the window looks the same as before, unless the
+        // selected show is gone.
+        if self.ordered_keys.contains(&old.selected_key) {
+            self.selected_key = old.selected_key;
+            self.selected_episodes = old.selected_episodes;
+        }
+        self.error = old.error;
+        self.notice = old.notice;
+        self.about_window_is_open = old.about_window_is_open;
+        self.history_window_is_open = old.history_window_is_open;
+        self.history = old.history;
+        self.episodes_window_is_open = old.episodes_window_is_open;
+        self.show_editor = old.show_editor;
+        // NOTE: the snapshot was just updated so keep the episodes
+        // that were new when pls started.
+        for (key, episodes) in old.new_episodes {
+            if self.shows.contains_key(&key) {
+                let new = self.new_episodes.entry(key).or_default();
+                for episode in episodes {
+                    if !new.contains(&episode) {
+                        new.push(episode);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Watch the config directory and all the show directories and
+    /// apply the changes in `apply_fs_changes`. `on_change` is called
+    /// (from a background thread) whenever there are changes to apply.
+    pub fn start_watching(&mut self, on_change: impl Fn() + Send + 'static) -> anyhow::Result<()> {
+        self.watcher = Some(Watcher::new(on_change)?);
+        self.update_watches();
+        Ok(())
+    }
+
+    /// Point the watcher at the current config and show directories.
+    fn update_watches(&mut self) {
+        let mut paths = vec![(self.config_dir().to_path_buf(), false)];
+        for show in self.shows.values() {
+            let path = match &show.playlist_file {
+                Some(playlist_file) => (playlist_file.clone(), false),
+                None => (show.dir.clone(), true),
+            };
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        if let Some(watcher) = &mut self.watcher {
+            watcher.watch(&paths);
+        }
+    }
+
+    /// Update the shows affected by the changes reported by the
+    /// watcher: reload the changed show config files (or everything
+    /// if `pls.toml` changed) and pick up new episodes.
+    pub fn apply_fs_changes(&mut self) -> anyhow::Result<()> {
+        let changes = match &self.watcher {
+            Some(watcher) => watcher.changes(),
+            None => return Ok(()),
+        };
+        // NOTE: the data directory (with the log and the progress) can
+        // be the config directory, e.g. on macOS. Only the config files
+        // matter there.
+        let config_dir = self.config_dir().to_path_buf();
+        let changes = changes
+            .into_iter()
+            .filter(|path| {
+                path.parent() != Some(config_dir.as_path())
+                    || (path.extension() == Some(OsStr::new("toml")) && config::is_show_file(path))
+                    || path == &self.config_path
+            })
+            .collect::<Vec<_>>();
+        if changes.is_empty() {
+            return Ok(());
+        }
+        log::info!("Applying file changes: {:?}", changes);
+
+        if changes.contains(&self.config_path) {
+            return self.reload_config();
+        }
+
+        let mut changed_keys = vec![];
+        for path in &changes {
+            if path.parent() == Some(config_dir.as_path())
+                && path.extension() == Some(OsStr::new("toml"))
+                && config::is_show_file(path)
+            {
+                if let Some(key) = path.file_stem().and_then(os_to_string) {
+                    self.reload_show_file(path, &key);
+                }
+                continue;
+            }
+            for (key, show) in &self.shows {
+                let affected = match &show.playlist_file {
+                    Some(playlist_file) => path == playlist_file,
+                    None => path.starts_with(&show.dir),
+                };
+                if affected && !changed_keys.contains(key) {
+                    changed_keys.push(key.clone());
+                }
+            }
+        }
+
+        for key in changed_keys {
+            let Some(show) = self.shows.get_mut(&key) else {
+                continue;
+            };
+            show.refresh_episodes();
+            self.repair_next_episode(&key)?;
+            let Some(show) = self.shows.get_mut(&key) else {
+                continue;
+            };
+            let episodes = show.episodes();
+            let new = self.new_episodes.entry(key.clone()).or_default();
+            for path in &changes {
+                if episodes.contains(path) && !new.contains(path) {
+                    new.push(path.clone());
+                }
+            }
+            self.resume_if_new_episodes(&key)?;
+        }
+        self.new_episodes.retain(|_, new| !new.is_empty());
+        self.update_watches();
+        Ok(())
+    }
+
+    /// If the show's `next` episode is gone (renamed or deleted),
+    /// replace it with the closest episode that's still there (see
+    /// `Show::find_replacement`), save it and add it to `repairs`. The
+    /// replacement can be undone. Episodes whose whole directory is
+    /// gone are left alone.
+    pub fn repair_next_episode(&mut self, key: &str) -> anyhow::Result<()> {
+        let Some(show) = self.shows.get_mut(key) else {
+            return Ok(());
+        };
+        let current = show.current_episode();
+        if current.is_file() {
+            return Ok(());
+        }
+        // NOTE: the whole directory is gone, e.g. the drive isn't
+        // plugged in. The episode is likely still there.
+        if current.parent().is_some_and(|dir| !dir.is_dir()) {
+            log::warn!(
+                "The directory of the next episode of `{key}` is gone: {}",
+                current.display()
+            );
+            return Ok(());
+        }
+        let Some(replacement) = show.find_replacement() else {
+            log::error!("Show `{key}` has no episodes to replace the missing `next` one");
+            return Ok(());
+        };
+        let missing = show.next.clone();
+        let finished = show.finished;
+        show.push_undo(UNDO_LENGTH);
+        show.set_next_episode(&replacement);
+        show.finished = finished;
+        let repair = Repair {
+            key: key.into(),
+            missing,
+            next: show.next.clone(),
+        };
+        log::warn!("{}", repair);
+        self.repairs.push(repair);
+        self.save_progress(key)
+    }
+
+    /// Scan the show's episodes again if they changed on disk since
+    /// the last scan. Called before changing the show so it never works
+    /// with an outdated list of episodes, e.g. when there's no watcher.
+    pub fn refresh_stale_episodes(&mut self, key: &str) -> anyhow::Result<()> {
+        if let Some(show) = self.shows.get_mut(key)
+            && show.refresh_episodes_if_stale()
+        {
+            self.repair_next_episode(key)?;
+            self.resume_if_new_episodes(key)?;
+        }
+        Ok(())
+    }
+
+    /// Resume the show if it's finished but has new episodes since and
+    /// save it.
+    fn resume_if_new_episodes(&mut self, key: &str) -> anyhow::Result<()> {
+        if let Some(show) = self.shows.get_mut(key)
+            && show.resume_if_new_episodes()
+        {
+            log::info!(
+                "Show `{}` has new episodes. Resuming at: {}",
+                key,
+                show.next.display()
+            );
+            self.save_progress(key)?;
+        }
+        Ok(())
+    }
+
+    /// Load the show with the given `key` from the config file at
+    /// `path` again after it was changed, created or deleted.
+    fn reload_show_file(&mut self, path: &Path, key: &str) {
+        // NOTE: the shows defined in `pls.toml` take precedence.
+        if self
+            .shows
+            .get(key)
+            .is_some_and(|show| show.config_path == self.config_path)
+        {
+            return;
+        }
+        log::info!("Reloading show `{}` from {}", key, path.display());
+        self.broken_shows.retain(|broken| broken.key != key);
+        if !path.exists() {
+            self.shows.remove(key);
+            return;
+        }
+        match Self::load_show_file(path, key, self.profile.as_deref(), &self.saved_progress) {
+            Ok(show) => {
+                self.shows.insert(key.into(), show);
+                if !self.ordered_keys.iter().any(|k| k == key) {
+                    self.ordered_keys.push(key.into());
+                }
+                if progress::show_table(&self.saved_progress, key).is_none()
+                    && let Err(error) = self.save_progress(key)
+                {
+                    log::error!("Could not save show `{}`: {}", key, error);
+                }
+                if let Err(error) = self
+                    .repair_next_episode(key)
+                    .and_then(|()| self.resume_if_new_episodes(key))
+                {
+                    log::error!("Could not save show `{}`: {}", key, error);
+                }
+            }
+            Err(error) => {
+                log::error!("Could not load show `{}`: {}", key, error);
+                self.shows.remove(key);
+                self.broken_shows.push(BrokenShow {
+                    key: key.into(),
+                    config_path: path.into(),
+                    error,
+                });
+                self.broken_shows.sort_by(|a, b| a.key.cmp(&b.key));
+            }
+        }
+    }
+
+    /// The directory holding `pls.toml` and the show config files.
+    /// The progress and the watch history are in `data_dir`.
+    pub fn config_dir(&self) -> &Path {
+        self.config_path.parent().unwrap_or(Path::new("."))
+    }
+
+    /// The player for the show with the given `key`: its own one or
+    /// the one from `pls.toml`. `None` means the episodes are opened
+    /// in the application associated with their file type.
+    pub fn player_for(&self, key: &str) -> Option<&Player> {
+        self.shows
+            .get(key)
+            .and_then(|show| show.player.as_ref())
+            .or(self.player.as_ref())
+    }
+
+    /// How long the player must run for the episode of the show with
+    /// the given `key` to count as watched.
+    pub fn min_watched_for(&self, key: &str) -> Option<Duration> {
+        self.shows
+            .get(key)
+            .and_then(|show| show.min_watched)
+            .or(self.min_watched)
+    }
+
+    /// The percentage of the episode that must be played for it to
+    /// count as watched if the show with the given `key` is played
+    /// with the mpv IPC integration. `None` if it's not.
+    pub fn mpv_for(&self, key: &str) -> Option<f64> {
+        let show = self.shows.get(key);
+        let enabled = show.and_then(|show| show.mpv_ipc).unwrap_or(self.mpv_ipc);
+        if !enabled {
+            return None;
+        }
+        if cfg!(not(unix)) {
+            log::warn!("The mpv IPC integration is only supported on Unix. Ignoring `mpv_ipc`.");
+            return None;
+        }
+        show.and_then(|show| show.watched_percent)
+            .or(self.watched_percent)
+            .or(Some(WATCHED_PERCENT_FALLBACK))
+    }
+
+    /// Play the next episode of the show with the given `key` and
+    /// record it in the watch history.
+    ///
+    /// If the episode is handed over to the application associated
+    /// with its file type, the show moves on to the next episode right
+    /// away. If it's played in a `player`, it's tracked in `playing`
+    /// and `on_exit` is called once the player exits (see
+    /// `finish_playbacks`).
+    pub fn play_next_episode(
+        &mut self,
+        key: &str,
+        on_exit: impl FnOnce() + Send + 'static,
+    ) -> anyhow::Result<()> {
+        self.refresh_stale_episodes(key)?;
+        let show = self
+            .shows
+            .get(key)
+            .ok_or_else(|| anyhow::anyhow!("Unknown show: `{key}`"))?;
+        if show.finished {
+            anyhow::bail!("The show `{key}` is finished. Restart it to watch it again.");
+        }
+        let episode = show.current_episode();
+        log::info!("Playing: {}", episode.display());
+
+        #[cfg(unix)]
+        if let Some(watched_percent) = self.mpv_for(key) {
+            crate::util::check_episode(&episode)?;
+            let player = self
+                .player_for(key)
+                .cloned()
+                .unwrap_or_else(crate::mpv::default_player);
+            let playback = crate::mpv::play(
+                key,
+                &episode,
+                &player,
+                show.position,
+                watched_percent,
+                on_exit,
+            )?;
+            self.playing.push(playback);
+            return self.record_play(key, &episode);
+        }
+
+        let min_watched = self.min_watched_for(key);
+        match crate::util::open_episode(&episode, self.player_for(key))? {
+            Some(child) => {
+                let playback = Playback::start(key, &episode, child, min_watched, on_exit);
+                self.playing.push(playback);
+            }
+            None => self.advance_past(key, &episode)?,
+        }
+        self.record_play(key, &episode)
+    }
+
+    /// Play the episode before the next one (i.e. the one watched
+    /// last) and record it in the watch history. The show doesn't
+    /// advance.
+    pub fn replay_previous_episode(&mut self, key: &str) -> anyhow::Result<PathBuf> {
+        let episode = self
+            .shows
+            .get(key)
+            .ok_or_else(|| anyhow::anyhow!("Unknown show: `{key}`"))?
+            .previous_episode()
+            .ok_or_else(|| anyhow::anyhow!("No previous episode for show `{key}`"))?;
+        log::info!("Replaying: {}", episode.display());
+        self.play_episode(key, &episode)?;
+        Ok(episode)
+    }
+
+    /// Play any `episode` of the show and record it in the watch
+    /// history. The show doesn't advance.
+    pub fn play_episode(&mut self, key: &str, episode: &Path) -> anyhow::Result<()> {
+        log::info!("Playing without advancing: {}", episode.display());
+        if let Some(child) = crate::util::open_episode(episode, self.player_for(key))? {
+            // NOTE: we only wait for the player so it doesn't stay
+            // around as a zombie process.
+            Playback::start(key, episode, child, None, || {});
+        }
+        self.record_play(key, episode)
+    }
+
+    /// Set the show's `next` episode to the given one (a full path)
+    /// and save it.
+    pub fn set_next_episode(&mut self, key: &str, episode: &Path) -> anyhow::Result<()> {
+        self.refresh_stale_episodes(key)?;
+        let show = self
+            .shows
+            .get_mut(key)
+            .ok_or_else(|| anyhow::anyhow!("Unknown show: `{key}`"))?;
+        if show.index.position(episode).is_none() {
+            anyhow::bail!(
+                "Episode `{}` is not part of the show `{key}`",
+                episode.display()
+            );
+        }
+        if show.current_episode() != episode || show.finished {
+            show.push_undo(UNDO_LENGTH);
+            show.set_next_episode(episode);
+        }
+        self.save_progress(key)
+    }
+
+    /// Start watching the show again from the first episode and save
+    /// it.
+    pub fn restart_show(&mut self, key: &str) -> anyhow::Result<()> {
+        self.refresh_stale_episodes(key)?;
+        let show = self
+            .shows
+            .get_mut(key)
+            .ok_or_else(|| anyhow::anyhow!("Unknown show: `{key}`"))?;
+        show.push_undo(UNDO_LENGTH);
+        show.restart();
+        self.save_progress(key)
+    }
+
+    /// Mark all the given episodes as watched, i.e. move `next` to the
+    /// episode following the last of them. If that's the show's last
+    /// episode, it stays the `next` one and the show is finished.
+    pub fn mark_watched(&mut self, key: &str, episodes: &[PathBuf]) -> anyhow::Result<()> {
+        self.refresh_stale_episodes(key)?;
+        let show = self
+            .shows
+            .get_mut(key)
+            .ok_or_else(|| anyhow::anyhow!("Unknown show: `{key}`"))?;
+        let all_episodes = show.episodes();
+        let last_index = episodes
+            .iter()
+            .filter_map(|episode| show.index.position(episode))
+            .max()
+            .ok_or_else(|| anyhow::anyhow!("No episodes of the show `{key}` selected"))?;
+        // NOTE: the last episode was watched, i.e. the whole show.
+        let (next, finished) = match all_episodes.get(last_index + 1) {
+            Some(next) => (next.clone(), false),
+            None => (all_episodes[last_index].clone(), true),
+        };
+        if show.current_episode() != next || show.finished != finished {
+            show.push_undo(UNDO_LENGTH);
+            show.set_next_episode(&next);
+            show.finished = finished;
+        }
+        self.save_progress(key)
+    }
+
+    /// Move the show past the `episode` if it's still the next one
+    /// (i.e. it hasn't been advanced in the meantime) and save it.
+    pub fn advance_past(&mut self, key: &str, episode: &Path) -> anyhow::Result<()> {
+        self.refresh_stale_episodes(key)?;
+        if let Some(show) = self.shows.get_mut(key)
+            && show.current_episode() == episode
+        {
+            show.push_undo(UNDO_LENGTH);
+            show.advance_to_next_episode();
+            self.save_progress(key)?;
+        }
+        Ok(())
+    }
+
+    /// Undo the last change of the show's `next` episode (advancing
+    /// it, setting it or marking episodes as watched). If there's
+    /// nothing to undo, step back to the previous episode instead.
+    /// Returns the new `next` episode (a full path).
+    pub fn undo_last_advance(&mut self, key: &str) -> anyhow::Result<PathBuf> {
+        self.refresh_stale_episodes(key)?;
+        let show = self
+            .shows
+            .get_mut(key)
+            .ok_or_else(|| anyhow::anyhow!("Unknown show: `{key}`"))?;
+        if !show.undo_next_episode() {
+            log::info!("Nothing to undo for show `{key}`, rewinding to the previous episode");
+            show.rewind_to_previous_episode();
+        }
+        let episode = show.current_episode();
+        self.save_progress(key)?;
+        Ok(episode)
+    }
+
+    /// Advance the shows whose players have exited after the episode
+    /// was watched. Shows whose players are still running stay as
+    /// they are. Shows that weren't watched long enough remember the
+    /// position to resume from (if known).
+    pub fn finish_playbacks(&mut self) -> anyhow::Result<()> {
+        let mut finished = vec![];
+        self.playing.retain(|playback| match playback.try_finish() {
+            Some(outcome) => {
+                finished.push((playback.key.clone(), playback.episode.clone(), outcome));
+                false
+            }
+            None => true,
+        });
+        for (key, episode, outcome) in finished {
+            self.finish_playback(&key, &episode, outcome)?;
+        }
         Ok(())
     }
 
-    pub fn save_config(&self, key: &str) -> anyhow::Result<()> {
-        log::info!("Saving config for show: {key}");
-        if let (Some(show), Some(config_dir)) = (self.shows.get(key), self.config_path.parent()) {
-            let show_path = config_dir.join(format!("{}.{}", key, "toml"));
-            log::debug!("Show path: {}", show_path.display());
-            let toml_src = fs::read_to_string(&show_path)?;
-            let mut doc = toml_src.parse::<Document>()?;
-            doc["next"] = toml_edit::value(show.next.display().to_string());
-            fs::write(&show_path, doc.to_string())?;
+    /// Block until all the players exit and then process them like
+    /// `finish_playbacks`.
+    pub fn wait_for_playbacks(&mut self) -> anyhow::Result<()> {
+        for playback in std::mem::take(&mut self.playing) {
+            let (key, episode) = (playback.key.clone(), playback.episode.clone());
+            let outcome = playback.wait();
+            self.finish_playback(&key, &episode, outcome)?;
         }
         Ok(())
     }
 
-    pub fn load_shows(show_dir: &Path) -> anyhow::Result<HashMap<String, Show>> {
+    fn finish_playback(
+        &mut self,
+        key: &str,
+        episode: &Path,
+        outcome: Outcome,
+    ) -> anyhow::Result<()> {
+        if outcome.watched {
+            return self.advance_past(key, episode);
+        }
+        log::info!(
+            "Not advancing show `{}`, `{}` wasn't watched",
+            key,
+            episode.display()
+        );
+        if let Some(show) = self.shows.get_mut(key)
+            && show.current_episode() == episode
+            && outcome.position.is_some()
+        {
+            show.position = outcome.position;
+            self.save_progress(key)?;
+        }
+        Ok(())
+    }
+
+    /// Append a play of the show's `episode` happening right now to
+    /// the watch history.
+    pub fn record_play(&self, key: &str, episode: &Path) -> anyhow::Result<()> {
+        history::record(&self.profile_dir(), &Play::now(key, episode))
+    }
+
+    /// The directory holding the progress and the watch history of the
+    /// current profile: `profiles/<profile>` in the data directory or
+    /// the data directory itself without a profile.
+    pub fn profile_dir(&self) -> PathBuf {
+        progress::profile_dir(&self.data_dir, self.profile.as_deref())
+    }
+
+    /// Switch to the progress and history of another `profile` (`None`
+    /// for the shows' own) and reload the shows.
+    pub fn switch_profile(&mut self, profile: Option<String>) -> anyhow::Result<()> {
+        log::info!("Switching to profile: {:?}", profile);
+        let previous = std::mem::replace(&mut self.profile, profile);
+        let result = self.reload_config();
+        if result.is_err() {
+            self.profile = previous;
+        }
+        self.history.clear();
+        result
+    }
+
+    /// Save the show's `next` episode, resume position, finished
+    /// state and undo history into the progress file of the current
+    /// profile. The show's config file is left alone. Does nothing
+    /// when the state is `read_only`.
+    pub fn save_progress(&mut self, key: &str) -> anyhow::Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        let dir = self.profile_dir();
+        match self.shows.get(key) {
+            Some(show) => progress::save(&dir, key, show, &mut self.saved_progress),
+            None => Ok(()),
+        }
+    }
+
+    /// Compare the episodes of the `shows` with the ones known the
+    /// last time pls ran and store the current ones (unless
+    /// `read_only`). Returns the new episodes for each show.
+    fn update_snapshot(
+        data_dir: &Path,
+        shows: &HashMap<String, Show>,
+        read_only: bool,
+    ) -> HashMap<String, Vec<PathBuf>> {
+        let new_episodes = match snapshot::read(data_dir) {
+            Ok(known) => snapshot::new_episodes(&known, shows),
+            Err(error) => {
+                log::error!("Could not read the known episodes: {}", error);
+                HashMap::new()
+            }
+        };
+        if read_only {
+            return new_episodes;
+        }
+        if let Err(error) = snapshot::write(data_dir, shows) {
+            log::error!("Could not write the known episodes: {}", error);
+        }
+        new_episodes
+    }
+
+    /// Load every show defined in the `[shows]` table of `pls.toml`
+    /// (at `config_path`, parsed into `doc`) and every show config
+    /// file next to it. Returns the loaded shows and the ones that
+    /// failed to load.
+    ///
+    /// If a show is defined in both places, the one in `pls.toml`
+    /// wins.
+    pub fn load_shows(
+        config_path: &Path,
+        doc: &Document,
+        profile: Option<&str>,
+        saved_progress: &Document,
+    ) -> anyhow::Result<(HashMap<String, Show>, Vec<BrokenShow>)> {
         let mut shows = HashMap::new();
-        for config_path in show_dir.read_dir()? {
-            log::debug!("Loading: {:?}", config_path);
-            match config_path {
-                Ok(config_path) => {
-                    if config_path.file_name() == "pls.toml" {
-                        log::debug!(
-                            "This is the main config file (pls.toml), not a show. Skipping."
-                        );
-                        continue;
-                    } else {
-                        log::info!("Loading show at path: {}", config_path.path().display());
-                        // TODO: Err handling
-                        if let Some(key) = config_path.path().file_stem().and_then(os_to_string) {
-                            log::debug!("Show key: {key}");
-                            let res = Self::load_show(&config_path.path(), &key).map(|show| {
-                                log::debug!("Loaded show: {:#?}", show);
-                                shows.insert(key, show);
-                            });
-                            log::debug!("Load show result: {:?}", res);
-                        }
-                    }
+        let mut broken_shows = vec![];
+        let show_dir = config_path.parent().unwrap_or(Path::new("."));
+        for path in config::toml_files(show_dir)? {
+            log::debug!("Loading: {:?}", path);
+            if !config::is_show_file(&path) {
+                log::debug!(
+                    "This is pls.toml or a file with pls's own data, not a show. Skipping."
+                );
+                continue;
+            }
+            log::info!("Loading show at path: {}", path.display());
+            let Some(key) = path.file_stem().and_then(os_to_string) else {
+                log::error!("Invalid show file name: {}", path.display());
+                continue;
+            };
+            log::debug!("Show key: {key}");
+            match Self::load_show_file(&path, &key, profile, saved_progress) {
+                Ok(show) => {
+                    log::debug!("Loaded show: {:#?}", show);
+                    shows.insert(key, show);
                 }
                 Err(error) => {
-                    log::error!("Error: {:?}", error);
+                    log::error!("Could not load show `{}`: {}", key, error);
+                    broken_shows.push(BrokenShow {
+                        key,
+                        config_path: path,
+                        error,
+                    });
                 }
             }
         }
 
-        Ok(shows)
+        if let Some(inline_shows) = doc.get("shows") {
+            let inline_shows =
+                inline_shows
+                    .as_table_like()
+                    .ok_or_else(|| ConfigError::InvalidValue {
+                        key: "shows".into(),
+                        message: "expected a table".into(),
+                    })?;
+            for (key, item) in inline_shows.iter() {
+                log::info!("Loading show `{}` from {}", key, config_path.display());
+                let result = item
+                    .as_table_like()
+                    .ok_or_else(|| ConfigError::InvalidValue {
+                        key: format!("shows.{key}"),
+                        message: "expected a table".into(),
+                    })
+                    .and_then(|table| {
+                        ShowConfig::from_table(table)?
+                            .with_profile(table, profile)?
+                            .with_progress(progress::show_table(saved_progress, key))
+                    })
+                    .and_then(|show_config| Self::load_show(config_path, key, &show_config));
+                match result {
+                    Ok(show) => {
+                        log::debug!("Loaded show: {:#?}", show);
+                        if shows.contains_key(key) {
+                            log::warn!(
+                                "Show `{key}` is defined both in `{key}.toml` and in `pls.toml`. Using the one in `pls.toml`."
+                            );
+                        }
+                        shows.insert(key.to_string(), show);
+                    }
+                    Err(error) => {
+                        log::error!("Could not load show `{}`: {}", key, error);
+                        broken_shows.push(BrokenShow {
+                            key: key.into(),
+                            config_path: config_path.into(),
+                            error,
+                        });
+                    }
+                }
+            }
+        }
+
+        broken_shows.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok((shows, broken_shows))
     }
 
-    pub fn load_show(path: &Path, key: &str) -> anyhow::Result<Show> {
-        let toml = fs::read_to_string(path)?;
-        let doc = toml.parse::<Document>()?;
-        let name = doc.get("name").and_then(|v| v.as_str());
-        let dir_default = doc.get("directory").and_then(|v| v.as_str());
-        log::debug!("dir_default: {:?}", &dir_default);
-        let hostname = hostname::get()
-            .ok()
-            .and_then(|cstr| cstr.into_string().ok());
+    /// Load the show with the given `key` from its own config file
+    /// with the progress of the given `profile` from `saved_progress`
+    /// (see [`crate::progress`]).
+    pub fn load_show_file(
+        path: &Path,
+        key: &str,
+        profile: Option<&str>,
+        saved_progress: &Document,
+    ) -> Result<Show, ConfigError> {
+        let show_doc = config::read_document(path)?;
+        let table = show_doc.as_table();
+        let show_config = ShowConfig::from_table(table)?
+            .with_profile(table, profile)?
+            .with_progress(progress::show_table(saved_progress, key))?;
+        Self::load_show(path, key, &show_config)
+    }
+
+    /// Build the show with the given `key` from its config. `path` is
+    /// the file it's defined in.
+    pub fn load_show(path: &Path, key: &str, config: &ShowConfig) -> Result<Show, ConfigError> {
+        let hostname = crate::util::hostname();
         log::debug!("hostname: {:?}", &hostname);
-        let dir_hostname = hostname
-            .clone()
-            .and_then(|hostname| doc.get(&format!("directory_{}", hostname)))
-            .map(|v| v.as_str())
-            .unwrap_or(dir_default);
-        log::debug!("dir_hostname: {:?}", &dir_hostname);
-
-        let name = name.unwrap_or_else(|| {
+
+        // NOTE: a show can point to a playlist file instead of a
+        // directory.
+        let location = config.location(hostname.as_deref());
+        log::debug!("location: {:?}", &location);
+        let playlist_file = match location {
+            Some(Location::Playlist(playlist_file)) => {
+                let playlist_file = PathBuf::from(playlist_file);
+                if !crate::playlist::is_playlist(&playlist_file) {
+                    return Err(ConfigError::UnsupportedPlaylist(playlist_file));
+                }
+                let playlist_file = playlist_file
+                    .canonicalize()
+                    .map_err(|_| ConfigError::NonexistentPlaylist(playlist_file))?;
+                Some(playlist_file)
+            }
+            _ => None,
+        };
+
+        let name = config.name.as_deref().unwrap_or_else(|| {
             log::warn!(
                 "The show doesn't have a `name` set. Using the `key` as fallback: `{}`",
                 key
             );
             key
         });
-        let next = doc.get("next").and_then(|v| v.as_str());
-
-        if let Some(dir) = dir_hostname.and_then(|dir| PathBuf::from(dir).canonicalize().ok()) {
-            // Fallback to the first file if no `next` key specified:
-            let next = next.map_or_else(
-                || {
-                    let first = crate::util::all_files_in_dir(&dir)
-                        .first()
-                        .map(String::from);
-                    log::warn!("No `next` key specified for show `{}`", key);
-                    log::info!(
-                        "Falling back to the first file in the directory: `{:?}`.",
-                        first
-                    );
-                    first
-                },
-                |s| Some(String::from(s)),
-            );
+        let filter =
+            EpisodeFilter::new(config.extensions.clone(), &config.ignore).map_err(|error| {
+                ConfigError::InvalidValue {
+                    key: "ignore".into(),
+                    message: error.to_string(),
+                }
+            })?;
+        let sort = match config.sort {
+            Some(sort) => sort,
+            None if playlist_file.is_some() => Sort::Playlist,
+            None => Sort::default(),
+        };
+        let playlist = config
+            .playlist
+            .iter()
+            .map(|entry| {
+                PathBuf::from(entry.replace(&['\\', '/'][..], std::path::MAIN_SEPARATOR_STR))
+            })
+            .collect::<Vec<_>>();
+        let player = config
+            .player
+            .as_ref()
+            .map(|command| Player::new(command))
+            .transpose()
+            .map_err(|error| ConfigError::InvalidValue {
+                key: "player".into(),
+                message: error.to_string(),
+            })?;
+        if sort == Sort::Playlist && playlist.is_empty() && playlist_file.is_none() {
+            log::warn!("Show `{key}` is sorted by `playlist` but has no `playlist` entries");
+        }
 
-            if let Some(next) = next {
-                let next = next.replace(&['\\', '/'][..], std::path::MAIN_SEPARATOR_STR);
-                return Ok(Show {
-                    name: name.into(),
-                    dir,
-                    next: next.into(),
-                });
-            } else {
-                log::error!("Error 1: could not load show `{}`:", key);
-                log::error!(
-                    "No `next` key and couldn't load the first show in directory `{}`",
-                    dir.display()
+        let dir = match (&playlist_file, location) {
+            (Some(playlist_file), _) => playlist_file
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+            (None, Some(Location::Directory(dir))) => {
+                let dir = PathBuf::from(dir);
+                dir.canonicalize()
+                    .map_err(|_| ConfigError::NonexistentDirectory(dir))?
+            }
+            (None, _) => return Err(ConfigError::MissingLocation { hostname }),
+        };
+
+        let mut show = Show {
+            name: name.into(),
+            config_path: path.into(),
+            dir,
+            next: PathBuf::new(),
+            position: None,
+            finished: false,
+            undo: config
+                .undo
+                .iter()
+                .map(|entry| {
+                    PathBuf::from(entry.replace(&['\\', '/'][..], std::path::MAIN_SEPARATOR_STR))
+                })
+                .collect(),
+            playlist_file,
+            filter,
+            sort,
+            playlist,
+            player,
+            min_watched: config.min_watched,
+            mpv_ipc: config.mpv_ipc,
+            watched_percent: config.watched_percent,
+            index: EpisodeIndex::default(),
+        };
+        show.refresh_episodes();
+
+        // Fallback to the first episode if no `next` key specified:
+        let next = match &config.next {
+            Some(next) => next.replace(&['\\', '/'][..], std::path::MAIN_SEPARATOR_STR),
+            None => {
+                let first = show.episodes().first().cloned();
+                log::warn!("No `next` key specified for show `{}`", key);
+                log::info!(
+                    "Falling back to the first episode in the directory: `{:?}`.",
+                    first
                 );
+                match first {
+                    Some(first) => first.display().to_string(),
+                    None => return Err(ConfigError::NoEpisodes(show.location().into())),
+                }
             }
-        } else {
-            log::error!("Error 2: could not load show `{}`:", key);
-            log::error!(
-                "Neither the `directory`, nor `directory_{}` key was specified.",
-                hostname.unwrap_or_else(|| "hostname".to_string())
-            );
+        };
+        show.set_next_episode(Path::new(&next));
+        // NOTE: a missing `next` episode can be replaced by another one
+        // (see `repair_next_episode`) unless there are none.
+        if !show.current_episode().is_file() && show.find_replacement().is_none() {
+            return Err(ConfigError::MissingNext {
+                next: show.next,
+                dir: show.dir,
+            });
+        }
+        // NOTE: the show couldn't advance past an episode it doesn't
+        // know about.
+        if show.current_episode().is_file()
+            && show.index.position(&show.current_episode()).is_none()
+        {
+            return Err(ConfigError::NextFiltered {
+                next: show.next,
+                dir: show.dir,
+            });
         }
+        show.position = config.position;
+        show.finished = config.finished;
+        Ok(show)
+    }
+}
+
+/// The `next` episode of a show that was gone and the one that
+/// replaced it.
+#[derive(Debug)]
+pub struct Repair {
+    pub key: String,
+    /// The missing episode, relative to the show's directory.
+    pub missing: PathBuf,
+    /// The new `next` episode, relative to the show's directory.
+    pub next: PathBuf,
+}
+
+impl std::fmt::Display for Repair {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "The next episode of `{}` is gone: {}. Continuing with: {}",
+            self.key,
+            self.missing.display(),
+            self.next.display()
+        )
+    }
+}
 
-        // TODO: replace some of the log issues above with bail as well?
-        anyhow::bail!("Could not load show! TODO: better error message.")
+/// A show that couldn't be loaded and why.
+#[derive(Debug)]
+pub struct BrokenShow {
+    pub key: String,
+    /// The file the show is defined in.
+    pub config_path: PathBuf,
+    pub error: ConfigError,
+}
+
+/// Move the watch history and the known episodes from the config
+/// directory, where they used to be, into the data directory. Files
+/// already in the data directory are not overwritten.
+fn move_data_files(config_dir: &Path, data_dir: &Path) {
+    if config_dir == data_dir {
+        return;
+    }
+    let mut files = vec![
+        PathBuf::from(history::HISTORY_FILE_NAME),
+        PathBuf::from(snapshot::SNAPSHOT_FILE_NAME),
+    ];
+    if let Ok(entries) = fs::read_dir(config_dir.join("profiles")) {
+        for entry in entries.flatten() {
+            files.push(
+                Path::new("profiles")
+                    .join(entry.file_name())
+                    .join(history::HISTORY_FILE_NAME),
+            );
+        }
+    }
+    for file in files {
+        let (from, to) = (config_dir.join(&file), data_dir.join(&file));
+        if !from.is_file() || to.exists() {
+            continue;
+        }
+        log::info!("Moving {} to {}", from.display(), to.display());
+        let result = to
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::rename(&from, &to))
+            // NOTE: renaming fails across filesystems.
+            .or_else(|_| fs::copy(&from, &to).and_then(|_| fs::remove_file(&from)));
+        if let Err(error) = result {
+            log::error!("Could not move {}: {}", from.display(), error);
+        }
     }
 }
 
+/// Convert the OS string into `String` if it's valid Unicode.
 pub fn os_to_string<T: AsRef<OsStr>>(os_str: T) -> Option<String> {
     os_str.as_ref().to_os_string().into_string().ok()
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum Version {
-    V1_0_0,
-}
+/// How many changes of a show's `next` episode can be undone.
+const UNDO_LENGTH: usize = 10;
 
-impl Version {
-    pub fn from_str(version_str: &str) -> Option<Self> {
-        match version_str {
-            "1.0.0" => Some(Version::V1_0_0),
-            _ => None,
+/// The percentage of the episode that must be played in mpv for it
+/// to count as watched if `watched_percent` isn't set.
+const WATCHED_PERCENT_FALLBACK: f64 = 90.0;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::util::test_dir;
+
+    use toml_edit::Item;
+
+    /// Load a config directory with a single show `show` with the
+    /// episode `files` from a temporary directory for the test called
+    /// `name`. `progress` is added to the show's config.
+    fn load(name: &str, files: &[&str], progress: &str) -> (PathBuf, State) {
+        let dir = test_dir(name);
+        let (config_dir, show_dir) = (dir.join("config"), dir.join("show"));
+        fs::create_dir_all(&config_dir).unwrap();
+        fs::create_dir_all(&show_dir).unwrap();
+        for file in files {
+            fs::write(show_dir.join(file), "").unwrap();
         }
+        fs::write(
+            config_dir.join(crate::CONFIG_FILE_NAME),
+            "version = \"2.0.0\"\n",
+        )
+        .unwrap();
+        fs::write(
+            config_dir.join("show.toml"),
+            format!(
+                "name = \"Show\"\ndirectory = {:?}\n{progress}",
+                show_dir.display().to_string()
+            ),
+        )
+        .unwrap();
+        let state = State::from_config_dir(&config_dir, &dir.join("data"), None, false).unwrap();
+        (dir, state)
     }
 
-    pub fn fallback() -> Self {
-        Version::V1_0_0
+    /// The show's `next` episode and whether it's finished, as saved in
+    /// the progress file.
+    fn saved(state: &State) -> (String, bool) {
+        let progress = progress::read(&state.profile_dir()).unwrap();
+        let table = progress::show_table(&progress, "show").unwrap();
+        (
+            table.get("next").and_then(Item::as_str).unwrap().into(),
+            table
+                .get("finished")
+                .and_then(Item::as_bool)
+                .unwrap_or(false),
+        )
     }
-}
 
-impl fmt::Display for Version {
-    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        use Version::*;
-        let s = match self {
-            V1_0_0 => "1.0.0",
-        };
-        write!(f, "{}", s)
+    #[test]
+    fn rewind_from_finished() {
+        let files = ["E1.mkv", "E2.mkv"];
+        let (dir, mut state) = load(
+            "state-rewind",
+            &files,
+            "next = \"E2.mkv\"\nfinished = true\n",
+        );
+        let episode = state.undo_last_advance("show").unwrap();
+        assert_eq!(episode, dir.join("show/E2.mkv"));
+        assert_eq!(saved(&state), ("E2.mkv".into(), false));
+        state.undo_last_advance("show").unwrap();
+        assert_eq!(saved(&state), ("E1.mkv".into(), false));
+        // NOTE: there's nothing before the first episode.
+        state.undo_last_advance("show").unwrap();
+        assert_eq!(saved(&state), ("E1.mkv".into(), false));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn undo_restart() {
+        let files = ["E1.mkv", "E2.mkv", "E3.mkv"];
+        let (dir, mut state) = load("state-undo-restart", &files, "next = \"E3.mkv\"\n");
+        state.restart_show("show").unwrap();
+        assert_eq!(saved(&state), ("E1.mkv".into(), false));
+        state.undo_last_advance("show").unwrap();
+        assert_eq!(saved(&state), ("E3.mkv".into(), false));
+        assert!(state.shows["show"].undo.is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn advance_to_finished() {
+        let files = ["E1.mkv", "E2.mkv"];
+        let (dir, mut state) = load("state-finish", &files, "next = \"E2.mkv\"\n");
+        state
+            .advance_past("show", &dir.join("show/E2.mkv"))
+            .unwrap();
+        assert_eq!(saved(&state), ("E2.mkv".into(), true));
+        assert_eq!(state.shows["show"].unwatched_count(), 0);
+        // NOTE: a finished show stays finished.
+        state
+            .advance_past("show", &dir.join("show/E2.mkv"))
+            .unwrap();
+        assert_eq!(saved(&state), ("E2.mkv".into(), true));
+        state.undo_last_advance("show").unwrap();
+        assert_eq!(saved(&state), ("E2.mkv".into(), false));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn mark_last_episode_watched() {
+        let files = ["E1.mkv", "E2.mkv", "E3.mkv"];
+        let (dir, mut state) = load("state-mark-watched", &files, "");
+        let episodes = [dir.join("show/E1.mkv"), dir.join("show/E2.mkv")];
+        state.mark_watched("show", &episodes).unwrap();
+        assert_eq!(saved(&state), ("E3.mkv".into(), false));
+        state
+            .mark_watched("show", &[dir.join("show/E3.mkv")])
+            .unwrap();
+        assert_eq!(saved(&state), ("E3.mkv".into(), true));
+        assert_eq!(state.shows["show"].undo.len(), 2);
+        state.undo_last_advance("show").unwrap();
+        assert_eq!(saved(&state), ("E3.mkv".into(), false));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resume_with_new_episodes() {
+        // NOTE: the episode was added while pls wasn't running.
+        let files = ["E1.mkv", "E2.mkv"];
+        let finished = "next = \"E1.mkv\"\nfinished = true\n";
+        let (dir, state) = load("state-resume", &files, finished);
+        assert_eq!(saved(&state), ("E2.mkv".into(), false));
+        fs::remove_dir_all(dir).unwrap();
+
+        // NOTE: the episode was added while pls was running.
+        let (dir, mut state) = load("state-resume-running", &files, "next = \"E2.mkv\"\n");
+        state
+            .mark_watched("show", &[dir.join("show/E2.mkv")])
+            .unwrap();
+        assert_eq!(saved(&state), ("E2.mkv".into(), true));
+        state.refresh_stale_episodes("show").unwrap();
+        assert_eq!(saved(&state), ("E2.mkv".into(), true));
+        fs::write(dir.join("show/E3.mkv"), "").unwrap();
+        state.refresh_stale_episodes("show").unwrap();
+        assert_eq!(saved(&state), ("E3.mkv".into(), false));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reload_keeps_window() {
+        let (dir, mut state) = load("state-reload", &["E1.mkv", "E2.mkv"], "");
+        state.error = Some("Error".into());
+        state.about_window_is_open = true;
+        state.episodes_window_is_open = true;
+        state.selected_episodes = vec![dir.join("show/E2.mkv")];
+        state.reload_config().unwrap();
+        assert_eq!(state.selected_key, "show");
+        assert_eq!(state.selected_episodes, [dir.join("show/E2.mkv")]);
+        assert_eq!(state.error.as_deref(), Some("Error"));
+        assert!(state.about_window_is_open);
+        assert!(state.episodes_window_is_open);
+
+        // NOTE: nothing's selected once the show is gone.
+        fs::remove_file(dir.join("config/show.toml")).unwrap();
+        state.reload_config().unwrap();
+        assert_eq!(state.selected_key, "");
+        assert!(state.selected_episodes.is_empty());
+        assert!(state.episodes_window_is_open);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn repair_missing_next() {
+        let files = ["E1.mkv", "E3.mkv"];
+        let (dir, mut state) = load("state-repair", &files, "next = \"E2.mkv\"\n");
+        assert_eq!(saved(&state), ("E3.mkv".into(), false));
+        assert_eq!(state.repairs.len(), 1);
+        state.undo_last_advance("show").unwrap();
+        assert_eq!(saved(&state), ("E2.mkv".into(), false));
+        fs::remove_dir_all(dir).unwrap();
+
+        // NOTE: the directory is likely on a drive that isn't plugged
+        // in.
+        let next = "next = \"Season 2/E1.mkv\"\n";
+        let (dir, state) = load("state-repair-unplugged", &files, next);
+        assert_eq!(saved(&state), ("Season 2/E1.mkv".into(), false));
+        assert!(state.repairs.is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_config() {
+        let dir = test_dir("state-missing-config");
+        let error = State::from_config_dir(&dir, &dir.join("data"), None, false).unwrap_err();
+        let path = dir.join(crate::CONFIG_FILE_NAME);
+        assert!(
+            error
+                .to_string()
+                .starts_with(&format!("Could not read `{}`", path.display())),
+            "{error}"
+        );
+        fs::remove_dir_all(dir).unwrap();
     }
 }
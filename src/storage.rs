@@ -0,0 +1,198 @@
+//! Crash-safe writing of the files pls keeps its config and data in.
+//!
+//! Files are written into a temporary file next to them first and
+//! only then renamed over the original, so an interrupted write leaves
+//! either the old or the new contents, never a truncated file. Writers
+//! take an advisory lock on the directory, so two running instances of
+//! pls don't write into the same files at once.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs::{self, File, OpenOptions, TryLockError},
+    hash::{Hash, Hasher},
+    io::{self, Write},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Name of the lock file inside the locked directory.
+pub const LOCK_FILE_NAME: &str = ".pls.lock";
+
+/// How long to wait for another instance of pls to release the lock.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Replace the contents of the file at `path` with `contents`.
+///
+/// The contents are written and flushed to the disk in a temporary
+/// file first, which is then renamed over `path`.
+pub fn write(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::other(format!("Not a file: {}", path.display())))?;
+    let temp_path = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+    let result =
+        write_and_sync(&temp_path, contents.as_ref()).and_then(|()| fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result?;
+    sync_dir(path.parent().unwrap_or(Path::new(".")))
+}
+
+fn write_and_sync(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+/// Flush the directory entries (i.e. the rename) to the disk.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+/// Windows doesn't support opening directories as files. The rename
+/// there is flushed with the file system's own journal.
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// An advisory lock on a directory. It's released when dropped.
+#[derive(Debug)]
+pub struct Lock {
+    _file: File,
+}
+
+/// Lock the directory `dir` (creating it if needed) for writing,
+/// waiting for a while if another instance of pls holds the lock.
+pub fn lock(dir: &Path) -> anyhow::Result<Lock> {
+    lock_with_timeout(dir, LOCK_TIMEOUT)
+}
+
+fn lock_with_timeout(dir: &Path, timeout: Duration) -> anyhow::Result<Lock> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(LOCK_FILE_NAME);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)?;
+    let start = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(Lock { _file: file }),
+            Err(TryLockError::WouldBlock) if start.elapsed() < timeout => {
+                log::debug!("Waiting for the lock on {}", dir.display());
+                thread::sleep(Duration::from_millis(50));
+            }
+            Err(TryLockError::WouldBlock) => {
+                anyhow::bail!(
+                    "Another instance of pls is writing into {}. Try again later.",
+                    dir.display()
+                )
+            }
+            Err(TryLockError::Error(error)) => return Err(error.into()),
+        }
+    }
+}
+
+/// Identifies the contents of a file, to tell whether it was changed
+/// by someone else since it was read.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Fingerprint(Option<u64>);
+
+impl Fingerprint {
+    /// The fingerprint of the file at `path`. A missing file has one
+    /// too (it changes once the file is created).
+    pub fn of(path: &Path) -> io::Result<Self> {
+        match fs::read(path) {
+            Ok(contents) => {
+                let mut hasher = DefaultHasher::new();
+                contents.hash(&mut hasher);
+                Ok(Fingerprint(Some(hasher.finish())))
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Fingerprint(None)),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::util::test_dir;
+
+    /// A crash between writing the temporary file and renaming it.
+    #[test]
+    fn interrupted_write() {
+        let dir = test_dir("interrupted-write");
+        let path = dir.join("progress.toml");
+        fs::write(&path, "next = \"Episode 1.mkv\"\n").unwrap();
+        let temp_path = dir.join(format!(".progress.toml.{}.tmp", std::process::id()));
+        write_and_sync(&temp_path, b"next = \"Epis").unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "next = \"Episode 1.mkv\"\n"
+        );
+
+        // NOTE: the next write replaces the left over temporary file.
+        write(&path, "next = \"Episode 2.mkv\"\n").unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "next = \"Episode 2.mkv\"\n"
+        );
+        assert!(!temp_path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_write() {
+        let dir = test_dir("failed-write");
+        let path = dir.join("progress.toml");
+        fs::write(&path, "next = \"Episode 1.mkv\"\n").unwrap();
+        // NOTE: the temporary file can't be created where a directory is.
+        let temp_path = dir.join(format!(".progress.toml.{}.tmp", std::process::id()));
+        fs::create_dir(&temp_path).unwrap();
+        assert!(write(&path, "next = \"Episode 2.mkv\"\n").is_err());
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "next = \"Episode 1.mkv\"\n"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn lock_timeout() {
+        let dir = test_dir("lock-timeout");
+        let lock = lock_with_timeout(&dir, Duration::from_millis(100)).unwrap();
+        let error = lock_with_timeout(&dir, Duration::from_millis(100)).unwrap_err();
+        assert!(
+            error.to_string().contains("Another instance of pls"),
+            "{error}"
+        );
+        drop(lock);
+        lock_with_timeout(&dir, Duration::from_millis(100)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn fingerprint() {
+        let dir = test_dir("fingerprint");
+        let path = dir.join("show.toml");
+        let missing = Fingerprint::of(&path).unwrap();
+        fs::write(&path, "name = \"Show\"\n").unwrap();
+        let written = Fingerprint::of(&path).unwrap();
+        assert_ne!(missing, written);
+        assert_eq!(written, Fingerprint::of(&path).unwrap());
+        fs::write(&path, "name = \"Other show\"\n").unwrap();
+        assert_ne!(written, Fingerprint::of(&path).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -2,47 +2,97 @@ use std::{
     fs::{self, DirEntry},
     io,
     path::{Path, PathBuf},
+    process::Child,
 };
 
 use humanesort::HumaneOrder;
 
-pub fn all_paths_in_dir(dir: &Path) -> Vec<PathBuf> {
+use crate::{filter::EpisodeFilter, player::Player};
+
+/// The name of this computer. `None` if it can't be determined or
+/// isn't valid Unicode.
+pub fn hostname() -> Option<String> {
+    hostname::get().ok().and_then(|h| h.into_string().ok())
+}
+
+/// Return all the files in `dir` and its subdirectories that pass the
+/// `filter`, sorted humanely (i.e. `2` goes before `10`).
+pub fn all_paths_in_dir(dir: &Path, filter: &EpisodeFilter) -> Vec<PathBuf> {
+    scan_dir(dir, filter).0
+}
+
+/// Like `all_paths_in_dir`, but also return all the visited
+/// directories (`dir` included).
+pub fn scan_dir(dir: &Path, filter: &EpisodeFilter) -> (Vec<PathBuf>, Vec<PathBuf>) {
     let mut result = vec![];
-    let _ = visit_dirs(dir, &mut |entry| {
+    let mut dirs = vec![];
+    let _ = visit_dirs(dir, &mut dirs, &mut |entry| {
         let path: PathBuf = entry.path();
         assert!(path.starts_with(dir));
-        result.push(path);
+        if filter.matches(dir, &path) {
+            result.push(path);
+        }
     });
 
-    result.sort_by(|a, b| {
-        HumaneOrder::humane_cmp(&a.display().to_string(), &b.display().to_string())
-    });
+    humane_sort(&mut result);
 
-    result
+    (result, dirs)
 }
 
-pub fn all_files_in_dir(dir: &Path) -> Vec<String> {
-    use humanesort::prelude::*;
-    let mut result = vec![];
-    let _ = visit_dirs(dir, &mut |entry| {
-        let path: PathBuf = entry.path();
-        assert!(path.starts_with(dir));
-        result.push(path.display().to_string());
+/// Sort the paths humanely (i.e. `2` goes before `10`).
+pub fn humane_sort(paths: &mut [PathBuf]) {
+    paths.sort_by(|a, b| {
+        HumaneOrder::humane_cmp(&a.display().to_string(), &b.display().to_string())
     });
+}
 
-    result.humane_sort();
+/// Make sure the episode exists and is a file.
+pub fn check_episode(episode: &Path) -> anyhow::Result<()> {
+    if !episode.exists() {
+        anyhow::bail!("Episode file doesn't exist: {}", episode.display());
+    } else if !episode.is_file() {
+        anyhow::bail!("Episode path is not a file: {}", episode.display());
+    }
+    Ok(())
+}
+
+/// Open the episode in the `player` or, if there's none, in the
+/// application associated with its file type.
+///
+/// Returns the player's process. There's none when the episode was
+/// handed over to the associated application.
+pub fn open_episode(episode: &Path, player: Option<&Player>) -> anyhow::Result<Option<Child>> {
+    check_episode(episode)?;
+    match player {
+        Some(player) => player.launch(episode).map(Some),
+        None => opener::open(episode)
+            .map(|_| None)
+            .map_err(|error| anyhow::anyhow!("Error opening file:\n{:?}", error)),
+    }
+}
 
-    result
+/// An empty temporary directory for the test called `name`.
+#[cfg(test)]
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pls-test-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
 }
 
 /// Adapted from: https://doc.rust-lang.org/std/fs/fn.read_dir.html#examples
-fn visit_dirs(dir: &Path, cb: &mut dyn FnMut(&DirEntry)) -> io::Result<()> {
+fn visit_dirs(
+    dir: &Path,
+    dirs: &mut Vec<PathBuf>,
+    cb: &mut dyn FnMut(&DirEntry),
+) -> io::Result<()> {
     if dir.is_dir() {
+        dirs.push(dir.to_path_buf());
         for entry in fs::read_dir(dir)? {
             let entry = entry?;
             let path = entry.path();
             if path.is_dir() {
-                visit_dirs(&path, cb)?;
+                visit_dirs(&path, dirs, cb)?;
             } else {
                 cb(&entry);
             }
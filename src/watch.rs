@@ -0,0 +1,112 @@
+//! Watching the config directory and the show directories for changes
+//! so they show up without reloading the whole config.
+
+use std::{
+    collections::BTreeSet,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher as _};
+
+/// How long the files must stay unchanged before the changes are
+/// reported. Copying a large episode produces a stream of events, this
+/// waits for it to finish.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watches a set of paths and reports the ones that changed in
+/// batches.
+pub struct Watcher {
+    watcher: RecommendedWatcher,
+    receiver: Receiver<Vec<PathBuf>>,
+    /// The watched paths and whether their subdirectories are watched
+    /// too.
+    watched: Vec<(PathBuf, bool)>,
+}
+
+impl Watcher {
+    /// Start a watcher (without any paths). `on_change` is called from
+    /// a background thread whenever a batch of changes is ready.
+    pub fn new(on_change: impl Fn() + Send + 'static) -> anyhow::Result<Self> {
+        let (event_sender, event_receiver) = mpsc::channel();
+        let watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) if !event.kind.is_access() => {
+                    let _ = event_sender.send(event.paths);
+                }
+                Ok(_) => {}
+                Err(error) => log::error!("File watcher error: {}", error),
+            })?;
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // NOTE: wait for the first change and then keep collecting
+            // them until nothing happens for `DEBOUNCE`.
+            while let Ok(paths) = event_receiver.recv() {
+                let mut batch = paths.into_iter().collect::<BTreeSet<_>>();
+                loop {
+                    match event_receiver.recv_timeout(DEBOUNCE) {
+                        Ok(paths) => batch.extend(paths),
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+                log::debug!("Files changed: {:?}", batch);
+                if sender.send(batch.into_iter().collect()).is_err() {
+                    return;
+                }
+                on_change();
+            }
+        });
+
+        Ok(Watcher {
+            watcher,
+            receiver,
+            watched: vec![],
+        })
+    }
+
+    /// Watch exactly the given paths (and the subdirectories of the
+    /// ones marked as recursive), dropping any other ones.
+    pub fn watch(&mut self, paths: &[(PathBuf, bool)]) {
+        for (path, recursive) in &self.watched {
+            if !paths.contains(&(path.clone(), *recursive))
+                && let Err(error) = self.watcher.unwatch(path)
+            {
+                log::warn!("Could not stop watching {}: {}", path.display(), error);
+            }
+        }
+        let mut watched = vec![];
+        for (path, recursive) in paths {
+            if self.watched.contains(&(path.clone(), *recursive)) {
+                watched.push((path.clone(), *recursive));
+                continue;
+            }
+            let mode = if *recursive {
+                RecursiveMode::Recursive
+            } else {
+                RecursiveMode::NonRecursive
+            };
+            match self.watcher.watch(path, mode) {
+                Ok(()) => watched.push((path.clone(), *recursive)),
+                Err(error) => log::warn!("Could not watch {}: {}", path.display(), error),
+            }
+        }
+        self.watched = watched;
+    }
+
+    /// All the paths that changed since the last call.
+    pub fn changes(&self) -> Vec<PathBuf> {
+        self.receiver.try_iter().flatten().collect()
+    }
+}
+
+impl std::fmt::Debug for Watcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Watcher")
+            .field("watched", &self.watched)
+            .finish()
+    }
+}
@@ -1,12 +1,26 @@
-use crate::state::State;
+use pls::{
+    editor::{self, ShowEditor},
+    history,
+    state::State,
+};
+
+use std::path::{Path, PathBuf};
 
 use egui::{
-    Align, Button, Color32, Layout, Rect, RichText, ScrollArea, Stroke, TextStyle, Ui, Vec2,
-    Widget, Window,
+    Align, Button, Color32, ComboBox, Grid, Id, Layout, Rect, RichText, ScrollArea, Stroke,
+    TextStyle, Ui, Vec2, Widget, Window,
 };
 
 pub fn show(state: &mut State, ui: &mut Ui) {
+    if let Err(error) = state.finish_playbacks() {
+        state.error = Some(format!("Error saving the config:\n{}", error));
+    }
+    if let Err(error) = state.apply_fs_changes() {
+        state.error = Some(format!("Error applying the changes on disk:\n{}", error));
+    }
+
     ui.style_mut().spacing.button_padding = [10.0, 10.0].into();
+    profile_switcher(state, ui);
     ui.heading("Select a show:");
     ui.add_space(5.0);
 
@@ -15,12 +29,25 @@ pub fn show(state: &mut State, ui: &mut Ui) {
         |ui| {
             ui.add_space(5.0);
             ui.allocate_ui_with_layout(Vec2::new(200.0, 30.0), Layout::left_to_right(), |ui| {
-                ui.columns(3, |c| {
+                ui.columns(4, |c| {
                     if c[0].button("About").clicked() {
                         state.about_window_is_open = true;
                     };
 
-                    if c[1].button("Config").clicked() {
+                    if c[1].button("History").clicked() {
+                        match history::read(&state.profile_dir()) {
+                            Ok(plays) => {
+                                state.history = plays;
+                                state.history_window_is_open = true;
+                            }
+                            Err(error) => {
+                                state.error =
+                                    Some(format!("Error reading the watch history:\n{}", error));
+                            }
+                        }
+                    };
+
+                    if c[2].button("Config").clicked() {
                         println!("Clicked: Config");
                         if let Err(error) = opener::open(&state.config_path) {
                             state.error =
@@ -28,7 +55,7 @@ pub fn show(state: &mut State, ui: &mut Ui) {
                         }
                     };
 
-                    if c[2].button("Reload").clicked()
+                    if c[3].button("Reload").clicked()
                         && let Err(error) = state.reload_config()
                     {
                         state.error = Some(format!("Error reloading the config:\n{}", error));
@@ -36,6 +63,38 @@ pub fn show(state: &mut State, ui: &mut Ui) {
                 });
             });
 
+            ui.allocate_ui_with_layout(Vec2::new(200.0, 30.0), Layout::left_to_right(), |ui| {
+                ui.columns(4, |c| {
+                    if c[0].button("Episodes").clicked() {
+                        state.selected_episodes.clear();
+                        state.episodes_window_is_open = true;
+                        let key = state.selected_key.clone();
+                        if let Err(error) = state.refresh_stale_episodes(&key) {
+                            state.error = Some(format!("Error saving the config:\n{}", error));
+                        }
+                    }
+
+                    if c[1].button("New show").clicked() {
+                        state.show_editor = Some(ShowEditor::new_show());
+                    }
+
+                    if c[2].button("Edit show").clicked() {
+                        match ShowEditor::edit_show(state, &state.selected_key) {
+                            Ok(show_editor) => state.show_editor = Some(show_editor),
+                            Err(error) => state.error = Some(error.to_string()),
+                        }
+                    }
+
+                    if c[3].button("Undo").clicked() {
+                        let key = state.selected_key.clone();
+                        if let Err(error) = state.undo_last_advance(&key) {
+                            state.error =
+                                Some(format!("Error undoing the last change:\n{}", error));
+                        }
+                    }
+                });
+            });
+
             ui.separator();
 
             let replay_last_text = state
@@ -47,79 +106,97 @@ pub fn show(state: &mut State, ui: &mut Ui) {
 
             if ui.button(replay_last_text).clicked() {
                 println!("Clicked: Replay last watched");
-                if let Some(episode) = state
-                    .shows
-                    .get_mut(&state.selected_key)
-                    .and_then(|show| show.previous_episode())
-                {
-                    println!("{}", episode.display());
-                    if !episode.exists() {
-                        state.error =
-                            Some(format!("Episode file doesn't exist: {}", episode.display()));
-                    } else if !episode.is_file() {
-                        state.error =
-                            Some(format!("Episode path is not a file: {}", episode.display()));
-                    } else if let Err(error) = opener::open(&episode) {
-                        state.error = Some(format!("Error opening file:\n{:?}", error));
-                    }
-                    println!("Opened: {:?}", episode.display());
-                    println!("Returning control back to pls");
+                let key = state.selected_key.clone();
+                match state.replay_previous_episode(&key) {
+                    Ok(episode) => println!("Opened: {:?}", episode.display()),
+                    Err(error) => state.error = Some(error.to_string()),
                 }
+                println!("Returning control back to pls");
             }
-            ui.label("Replay last watched:");
+            let replay_last_number = state
+                .shows
+                .get(&state.selected_key)
+                .and_then(|show| {
+                    show.previous_episode()
+                        .and_then(|e| show.episode_number(&e))
+                })
+                .map(|number| format!(" {}", number))
+                .unwrap_or_default();
+            ui.label(format!("Replay last watched:{}", replay_last_number));
 
-            let play_next_text = state
+            let is_finished = state
                 .shows
                 .get(&state.selected_key)
-                .map(|show| show.current_episode())
-                .and_then(|e| e.file_name().map(|f| f.to_string_lossy().into_owned()))
-                .unwrap_or_else(|| "No episode available".into());
+                .is_some_and(|show| show.finished);
+            let play_next_text = if is_finished {
+                "Restart from the beginning".into()
+            } else {
+                state
+                    .shows
+                    .get(&state.selected_key)
+                    .map(|show| show.current_episode())
+                    .and_then(|e| e.file_name().map(|f| f.to_string_lossy().into_owned()))
+                    .unwrap_or_else(|| "No episode available".into())
+            };
 
             let play_next_label = RichText::new(play_next_text)
                 .color(Color32::BLUE)
                 .text_style(TextStyle::Heading);
             let play_next_button = Button::new(play_next_label).ui(ui);
-            if play_next_button.clicked() {
+            if play_next_button.clicked() && is_finished {
+                log::debug!("Clicked: Restart");
+                let key = state.selected_key.clone();
+                if let Err(error) = state.restart_show(&key) {
+                    state.error = Some(error.to_string());
+                }
+            } else if play_next_button.clicked() {
                 println!("Clicked: Playing next");
-                if let Some(show) = state.shows.get_mut(&state.selected_key) {
-                    println!("Selected: {:?}", show);
-                    let current_episode = show.current_episode();
-                    println!("{}", current_episode.display());
-                    if !current_episode.exists() {
-                        state.error = Some(format!(
-                            "Episode file doesn't exist: {}",
-                            current_episode.display()
-                        ));
-                    } else if !current_episode.is_file() {
-                        state.error = Some(format!(
-                            "Episode path is not a file: {}",
-                            current_episode.display()
-                        ));
-                    } else if let Err(error) = opener::open(&current_episode) {
-                        state.error = Some(format!("Error opening file:\n{:?}", error));
-                    }
-                    println!("Opened: {:?}", current_episode.display());
-                    println!("Returning control back to pls");
-                    show.advance_to_next_episode();
-                    if let Some(table) = state
-                        .config
-                        .get_mut(&state.selected_key)
-                        .and_then(toml_edit::Item::as_table_mut)
-                    {
-                        table.insert("next", toml_edit::value(show.next.display().to_string()));
-                    }
-                    println!("{}", state.config);
-                    let _ = state.save_config(&state.selected_key);
+                // NOTE: with a player, the show is only advanced once it
+                // exits (see `State::finish_playbacks`).
+                let ctx = ui.ctx().clone();
+                let key = state.selected_key.clone();
+                if let Err(error) = state.play_next_episode(&key, move || ctx.request_repaint()) {
+                    state.error = Some(error.to_string());
                 }
+                println!("Returning control back to pls");
             };
-            ui.label("Play next episode:");
+            let play_next_number = state
+                .shows
+                .get(&state.selected_key)
+                .and_then(|show| show.episode_number(&show.current_episode()))
+                .map(|number| format!(" {}", number))
+                .unwrap_or_default();
+            let is_playing = state
+                .playing
+                .iter()
+                .any(|playback| playback.key == state.selected_key);
+            if is_playing {
+                ui.label("Waiting for the player to finish...");
+            }
+            let resume_at = state
+                .shows
+                .get(&state.selected_key)
+                .and_then(|show| show.position)
+                .map(|position| {
+                    let seconds = position as u64;
+                    format!(" (resume at {}:{:02})", seconds / 60, seconds % 60)
+                })
+                .unwrap_or_default();
+            if is_finished {
+                ui.label("Finished watching the show");
+            } else {
+                ui.label(format!(
+                    "Play next episode:{}{}",
+                    play_next_number, resume_at
+                ));
+            }
 
             ui.separator();
 
             ui.add_space(5.0);
 
             if let Some(show) = state.shows.get_mut(&state.selected_key) {
-                ui.label(format!("Location: {}", show.dir.display()));
+                ui.label(format!("Location: {}", show.location().display()));
             }
 
             ui.add_space(10.0);
@@ -136,13 +213,43 @@ pub fn show(state: &mut State, ui: &mut Ui) {
                         );
                         for key in &state.ordered_keys {
                             if let Some(show) = &state.shows.get(key) {
-                                ui.selectable_value(
-                                    &mut state.selected_key,
-                                    key.to_string(),
-                                    &show.name,
-                                );
+                                let new_count = state.new_episodes.get(key).map_or(0, Vec::len);
+                                let name = if show.finished {
+                                    RichText::new(format!("{} (finished)", show.name))
+                                        .color(Color32::GRAY)
+                                } else if new_count > 0 {
+                                    RichText::new(format!(
+                                        "{} ({} unwatched, {} new)",
+                                        show.name,
+                                        show.unwatched_count(),
+                                        new_count
+                                    ))
+                                    .color(Color32::DARK_GREEN)
+                                } else {
+                                    RichText::new(format!(
+                                        "{} ({} unwatched)",
+                                        show.name,
+                                        show.unwatched_count()
+                                    ))
+                                };
+                                ui.selectable_value(&mut state.selected_key, key.to_string(), name);
                             }
                         }
+                        if !state.broken_shows.is_empty() {
+                            ui.separator();
+                            ui.label("Broken shows:");
+                        }
+                        for broken in &state.broken_shows {
+                            ui.colored_label(
+                                Color32::DARK_RED,
+                                format!(
+                                    "{} ({}):\n{}",
+                                    broken.key,
+                                    broken.config_path.display(),
+                                    broken.error
+                                ),
+                            );
+                        }
                     });
                 });
         },
@@ -161,6 +268,70 @@ pub fn show(state: &mut State, ui: &mut Ui) {
         }
     }
 
+    let mut notice_window_is_open = state.notice.is_some();
+    if let Some(message) = state.notice.as_ref() {
+        Window::new("pls")
+            .open(&mut notice_window_is_open)
+            .collapsible(false)
+            .show(ui.ctx(), |ui| {
+                ui.label(message);
+            });
+        if !notice_window_is_open {
+            state.notice = None;
+        }
+    }
+
+    let mut repairs_window_is_open = !state.repairs.is_empty();
+    if repairs_window_is_open {
+        Window::new("Missing episodes")
+            .open(&mut repairs_window_is_open)
+            .collapsible(false)
+            .show(ui.ctx(), |ui| {
+                for repair in &state.repairs {
+                    ui.label(repair.to_string());
+                }
+            });
+        if !repairs_window_is_open {
+            state.repairs.clear();
+        }
+    }
+
+    let selected_key = &state.selected_key;
+    let history_title = match state.shows.get(selected_key) {
+        Some(show) => format!("History: {}", show.name),
+        None => "History".into(),
+    };
+    Window::new(history_title)
+        .id(Id::new("History"))
+        .open(&mut state.history_window_is_open)
+        .collapsible(false)
+        .show(ui.ctx(), |ui| {
+            ScrollArea::vertical()
+                .always_show_scroll(true)
+                .show(ui, |ui| {
+                    let plays = history::recent(&state.history, selected_key, HISTORY_LENGTH);
+                    if plays.is_empty() {
+                        ui.label("Nothing watched yet.");
+                    }
+                    for play in plays {
+                        let episode = play
+                            .episode
+                            .file_name()
+                            .map(|f| f.to_string_lossy().into_owned())
+                            .unwrap_or_default();
+                        ui.label(format!(
+                            "{}  {}  ({})",
+                            play.timestamp_str(),
+                            episode,
+                            play.hostname
+                        ));
+                    }
+                });
+        });
+
+    episodes_window(state, ui);
+    show_editor_window(state, ui);
+
     Window::new("About pls")
         .open(&mut state.about_window_is_open)
         .collapsible(false)
@@ -173,6 +344,256 @@ pub fn show(state: &mut State, ui: &mut Ui) {
         });
 }
 
+/// What to do with the episodes selected in the Episodes window.
+enum EpisodeAction {
+    SetNext(PathBuf),
+    MarkWatched(Vec<PathBuf>),
+    Play(PathBuf),
+}
+
+/// Pick whose progress and history to show. Only displayed when
+/// there are `profiles` in `pls.toml`.
+fn profile_switcher(state: &mut State, ui: &mut Ui) {
+    if state.profiles.is_empty() {
+        return;
+    }
+    let mut selected = state.profile.clone();
+    ComboBox::from_label("Profile")
+        .selected_text(selected.as_deref().unwrap_or(DEFAULT_PROFILE))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(&mut selected, None, DEFAULT_PROFILE);
+            for profile in &state.profiles {
+                ui.selectable_value(&mut selected, Some(profile.clone()), profile);
+            }
+        });
+    if selected != state.profile {
+        state.history_window_is_open = false;
+        if let Err(error) = state.switch_profile(selected) {
+            state.error = Some(format!("Error switching the profile:\n{}", error));
+        }
+    }
+}
+
+/// The window listing all the episodes of the selected show, grouped
+/// by their directory (or season). Any episode can be made the next
+/// one or played without advancing the show and a range of episodes
+/// can be marked as watched.
+fn episodes_window(state: &mut State, ui: &mut Ui) {
+    if !state.episodes_window_is_open {
+        return;
+    }
+    let key = state.selected_key.clone();
+    let show = match state.shows.get(&key) {
+        Some(show) => show,
+        None => return,
+    };
+    let episodes = show.episodes();
+    let current_episode = show.current_episode();
+    let title = format!("Episodes: {}", show.name);
+
+    // NOTE: group the consecutive episodes by their directory relative
+    // to the show. Fall back to the season for flat directories.
+    let mut groups: Vec<(String, Vec<&PathBuf>)> = vec![];
+    for episode in episodes {
+        let relative = episode.strip_prefix(&show.dir).unwrap_or(episode);
+        let group = match relative.parent().filter(|p| !p.as_os_str().is_empty()) {
+            Some(parent) => parent.display().to_string(),
+            None => match show.episode_number(episode).and_then(|n| n.season) {
+                Some(season) => format!("Season {}", season),
+                None => "Episodes".into(),
+            },
+        };
+        match groups.last_mut() {
+            Some((last, members)) if *last == group => members.push(episode),
+            _ => groups.push((group, vec![episode])),
+        }
+    }
+
+    let selected = &mut state.selected_episodes;
+    let mut action = None;
+    Window::new(title)
+        .id(Id::new("Episodes"))
+        .open(&mut state.episodes_window_is_open)
+        .collapsible(false)
+        .show(ui.ctx(), |ui| {
+            ui.label("Click to select an episode, shift+click to select a range.");
+            ui.horizontal(|ui| {
+                let first = selected.first().cloned();
+                if ui
+                    .add_enabled(selected.len() == 1, Button::new("Set as next"))
+                    .clicked()
+                    && let Some(first) = first.clone()
+                {
+                    action = Some(EpisodeAction::SetNext(first));
+                }
+                if ui
+                    .add_enabled(!selected.is_empty(), Button::new("Mark watched"))
+                    .clicked()
+                {
+                    action = Some(EpisodeAction::MarkWatched(selected.clone()));
+                }
+                if ui
+                    .add_enabled(selected.len() == 1, Button::new("Play"))
+                    .clicked()
+                    && let Some(first) = first
+                {
+                    action = Some(EpisodeAction::Play(first));
+                }
+            });
+            ui.separator();
+
+            ScrollArea::vertical()
+                .always_show_scroll(true)
+                .show(ui, |ui| {
+                    if episodes.is_empty() {
+                        ui.label("No episodes found.");
+                    }
+                    let shift = ui.input().modifiers.shift;
+                    for (group, members) in &groups {
+                        egui::CollapsingHeader::new(group)
+                            .default_open(true)
+                            .show(ui, |ui| {
+                                for episode in members {
+                                    let name = episode
+                                        .file_name()
+                                        .map(|f| f.to_string_lossy().into_owned())
+                                        .unwrap_or_default();
+                                    let mut text = RichText::new(name);
+                                    if **episode == current_episode {
+                                        text = text.color(Color32::BLUE).strong();
+                                    }
+                                    let is_selected = selected.contains(episode);
+                                    if ui.selectable_label(is_selected, text).clicked() {
+                                        select_episode(selected, episodes, episode, shift);
+                                    }
+                                }
+                            });
+                    }
+                });
+        });
+
+    let result = match action {
+        Some(EpisodeAction::SetNext(episode)) => state.set_next_episode(&key, &episode),
+        Some(EpisodeAction::MarkWatched(episodes)) => state.mark_watched(&key, &episodes),
+        Some(EpisodeAction::Play(episode)) => state.play_episode(&key, &episode),
+        None => Ok(()),
+    };
+    if let Err(error) = result {
+        state.error = Some(error.to_string());
+    }
+}
+
+/// Select the clicked `episode`. With `extend`, select all the
+/// episodes between the first selected one and the clicked one.
+fn select_episode(selected: &mut Vec<PathBuf>, episodes: &[PathBuf], episode: &Path, extend: bool) {
+    let anchor = selected
+        .first()
+        .and_then(|anchor| episodes.iter().position(|e| e == anchor));
+    let clicked = episodes.iter().position(|e| e == episode);
+    match (extend, anchor, clicked) {
+        (true, Some(anchor), Some(clicked)) => {
+            let range = if anchor <= clicked {
+                episodes[anchor..=clicked].to_vec()
+            } else {
+                episodes[clicked..=anchor].iter().rev().cloned().collect()
+            };
+            *selected = range;
+        }
+        _ => *selected = vec![episode.to_path_buf()],
+    }
+}
+
+/// The window for creating, editing and deleting a show.
+fn show_editor_window(state: &mut State, ui: &mut Ui) {
+    let mut show_editor = match state.show_editor.take() {
+        Some(show_editor) => show_editor,
+        None => return,
+    };
+    let title = match &show_editor.editing {
+        Some(_) => "Edit show",
+        None => "New show",
+    };
+    let hostname = pls::util::hostname().unwrap_or_else(|| "this computer".into());
+
+    let mut is_open = true;
+    let mut save_clicked = false;
+    let mut cancel_clicked = false;
+    let mut delete_clicked = false;
+    Window::new(title)
+        .id(Id::new("Show editor"))
+        .open(&mut is_open)
+        .collapsible(false)
+        .show(ui.ctx(), |ui| {
+            Grid::new("Show editor fields")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Key:");
+                    if show_editor.editing.is_some() {
+                        ui.label(&show_editor.key);
+                    } else {
+                        ui.text_edit_singleline(&mut show_editor.key);
+                    }
+                    ui.end_row();
+
+                    ui.label("Name:");
+                    ui.text_edit_singleline(&mut show_editor.name);
+                    ui.end_row();
+
+                    ui.label("Directory:");
+                    ui.text_edit_singleline(&mut show_editor.directory);
+                    ui.end_row();
+
+                    ui.label(format!("Directory on {}:", hostname));
+                    ui.text_edit_singleline(&mut show_editor.host_directory);
+                    ui.end_row();
+
+                    ui.label("Next episode:");
+                    ui.text_edit_singleline(&mut show_editor.next);
+                    ui.end_row();
+                });
+
+            ui.add_space(10.0);
+            ui.horizontal(|ui| {
+                save_clicked = ui.button("Save").clicked();
+                cancel_clicked = ui.button("Cancel").clicked();
+                if show_editor.editing.is_some() {
+                    let delete_text = if show_editor.confirm_delete {
+                        "Really delete?"
+                    } else {
+                        "Delete"
+                    };
+                    delete_clicked = ui.button(delete_text).clicked();
+                }
+            });
+        });
+
+    if save_clicked {
+        match show_editor.save(state) {
+            Ok(()) => return,
+            Err(error) => state.error = Some(format!("Error saving the show:\n{}", error)),
+        }
+    }
+    if delete_clicked {
+        if !show_editor.confirm_delete {
+            show_editor.confirm_delete = true;
+        } else if let Some(key) = &show_editor.editing {
+            match editor::delete_show(state, key) {
+                Ok(()) => return,
+                Err(error) => state.error = Some(format!("Error deleting the show:\n{}", error)),
+            }
+        }
+    }
+    if is_open && !cancel_clicked {
+        state.show_editor = Some(show_editor);
+    }
+}
+
+/// Number of the most recent plays listed in the History window.
+const HISTORY_LENGTH: usize = 100;
+
+/// How the shows' own progress (i.e. no profile) is labelled.
+const DEFAULT_PROFILE: &str = "Default";
+
 const ABOUT_TEXT: &str = "pls is a program that lets you load up series of files (typically video files) and play those files one after another. For example, if you've got the episodes of a TV show in a directory, it will play them one by one without you having to remember where left off.
 
 Copyright (C) 2019-2022 Tomas Sedovic <tomas@sedovic.cz>